@@ -0,0 +1,108 @@
+//! Compares a tick on a fully packed 200x60 screen between the dense [`Grid`] and the
+//! `HashMap<u32, Cell>` layout the simulation used before. The packed screen settles right away, so
+//! the simulation is woken before every tick to visit every particle like the hash map does.
+//!
+//! Run with `cargo bench --bench grid`.
+
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use sandbox::{
+    coord::{Direction, Vec2},
+    material::MaterialRegistry,
+    particle::Cell,
+    simulation_widget::{MaterialCanvas, Simulation},
+    window::Window,
+};
+
+const WIDTH: u16 = 200;
+const HEIGHT: u16 = 60;
+const TICKS: u32 = 200;
+
+fn packed_screen() -> Vec<Vec2> {
+    (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| Vec2::from((x, y))))
+        .collect()
+}
+
+fn key(pos: &Vec2) -> u32 {
+    (pos.x as u32) << 16 | pos.y as u32
+}
+
+/// The previous implementation: a hash map keyed by the packed coordinates and a freshly allocated
+/// neighbour map for every particle.
+fn hashmap_tick(src: &HashMap<u32, Cell>, dst: &mut HashMap<u32, Cell>) {
+    dst.clear();
+    for (idx, cell) in src.iter() {
+        let pos = Vec2 {
+            x: (idx >> 16) as i16,
+            y: (idx & 0xffff) as i16,
+        };
+        let mut neighbours: HashMap<Direction, &Cell> = HashMap::new();
+        for direction in Direction::ALL {
+            let other = &pos + Vec2::from(direction);
+            if other.x < 0 || other.y < 0 || other.x >= WIDTH as i16 || other.y >= HEIGHT as i16 {
+                neighbours.insert(direction, &sandbox::particle::BORDER);
+            } else if let Some(cell) = src.get(&key(&other)) {
+                neighbours.insert(direction, cell);
+            }
+        }
+        let target = if neighbours.contains_key(&Direction::Down) {
+            pos
+        } else {
+            &pos + Vec2::from(Direction::Down)
+        };
+        dst.insert(key(&target), cell.clone());
+    }
+}
+
+fn measure(name: &str, mut tick: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        tick();
+    }
+    let per_tick = start.elapsed() / TICKS;
+    println!("{name:<10} {per_tick:>12.2?} per tick");
+    per_tick
+}
+
+fn main() {
+    let points = packed_screen();
+    let materials = MaterialRegistry::default();
+    let sand = materials.find("sand").expect("sand is a built-in material");
+
+    let mut src: HashMap<u32, Cell> = points
+        .iter()
+        .map(|pos| (key(pos), Cell::new(sand, &materials, &mut rand::rng())))
+        .collect();
+    let mut dst = HashMap::new();
+    let hashmap = measure("hashmap", || {
+        hashmap_tick(&src, &mut dst);
+        std::mem::swap(&mut src, &mut dst);
+        black_box(&src);
+    });
+
+    let mut simulation = Simulation::new(materials.clone());
+    simulation.update_window_size(Window {
+        height: HEIGHT,
+        width: WIDTH,
+        x: 0,
+        y: 0,
+    });
+    simulation
+        .set_pixels(&points, sand)
+        .expect("the packed screen fits into the window");
+    let grid = measure("grid", || {
+        simulation.wake_all();
+        simulation.handle_ticks().expect("tick should succeed");
+        black_box(&simulation);
+    });
+
+    println!(
+        "speed-up: {:.1}x",
+        hashmap.as_secs_f64() / grid.as_secs_f64()
+    );
+}
@@ -0,0 +1,70 @@
+//! Compares a tick on a large, busy screen between updating the chunks row by row on a single
+//! thread and in checkerboard passes on all cores.
+//!
+//! Run with `cargo bench --bench parallel --features parallel`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use sandbox::{
+    coord::Vec2,
+    material::MaterialRegistry,
+    simulation_widget::{MaterialCanvas, Simulation},
+    window::Window,
+};
+
+const WIDTH: u16 = 800;
+const HEIGHT: u16 = 400;
+const TICKS: u32 = 100;
+
+/// Sand over water in alternating columns, so every chunk keeps moving for a while.
+fn busy_screen(parallel: bool) -> Simulation {
+    let materials = MaterialRegistry::default();
+    let sand = materials.find("sand").expect("sand is a built-in material");
+    let water = materials
+        .find("water")
+        .expect("water is a built-in material");
+
+    let mut simulation = Simulation::with_seed(materials, 7);
+    simulation.set_parallel(parallel);
+    simulation.update_window_size(Window {
+        height: HEIGHT,
+        width: WIDTH,
+        x: 0,
+        y: 0,
+    });
+    let (upper, lower): (Vec<Vec2>, Vec<Vec2>) = (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| Vec2::from((x, y))))
+        .filter(|pos| pos.x % 3 != 0)
+        .partition(|pos| pos.y < HEIGHT as i16 / 2);
+    simulation
+        .set_pixels(&upper, sand)
+        .expect("the sand fits into the window");
+    simulation
+        .set_pixels(&lower, water)
+        .expect("the water fits into the window");
+    simulation
+}
+
+fn measure(name: &str, mut simulation: Simulation) -> Duration {
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        simulation.handle_ticks().expect("tick should succeed");
+        black_box(&simulation);
+    }
+    let per_tick = start.elapsed() / TICKS;
+    println!("{name:<10} {per_tick:>12.2?} per tick");
+    per_tick
+}
+
+fn main() {
+    let single = measure("single", busy_screen(false));
+    let parallel = measure("parallel", busy_screen(true));
+
+    println!(
+        "speed-up: {:.1}x",
+        single.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
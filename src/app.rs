@@ -1,13 +1,19 @@
 use crate::{
-    event::{AppEvent, Event, EventHandler},
+    coord::{Direction, Vec2},
+    event::{AppEvent, Event, EventHandler, Speed},
+    recorder::{DEFAULT_EVERY, DEFAULT_MAX_FRAMES, Recorder},
     simulation_widget::Simulation,
+    stats::{DurationAverage, RateCounter},
+    ui::Palette,
 };
 use color_eyre::eyre;
 use ratatui::{
     DefaultTerminal,
-    crossterm::event::{KeyCode, KeyModifiers},
+    crossterm::event::{KeyCode, KeyModifiers, MouseButton, MouseEventKind},
+    layout::{Position, Rect},
     style::Color,
 };
+use std::{fs, path::PathBuf, time::Instant};
 
 /// Application.
 pub struct App {
@@ -16,19 +22,44 @@ pub struct App {
     /// Event handler.
     pub events: EventHandler,
     pub simulation_paused: bool,
+    /// How fast the simulation runs while it isn't paused.
+    pub speed: Speed,
+    /// Colour of the material selected for painting.
     pub color: Color,
     pub simulation_widget: Simulation,
+    /// World position shown in the upper left corner of the simulation area.
+    pub camera: Vec2,
+    /// Where the material palette was drawn last, clicks into it select a material.
+    pub palette_area: Rect,
+    /// Simulation steps per second.
+    pub tick_rate: RateCounter,
+    /// Rendered frames per second.
+    pub frame_rate: RateCounter,
+    /// How long a simulation step takes.
+    pub tick_time: DurationAverage,
+    /// File the world is saved to and loaded from.
+    pub save_path: PathBuf,
+    /// Pixels per cell of exported images.
+    pub image_scale: u32,
+    /// The timelapse being recorded, if any.
+    pub recorder: Option<Recorder>,
+    /// Ticks between two frames of a recording.
+    pub record_every: u32,
+    /// Frames after which a recording stops by itself.
+    pub max_frames: usize,
+    /// Outcome of the last save, load, export or recording, shown in the status bar until the next
+    /// key press.
+    pub message: Option<String>,
 }
 
+/// Number of cells the camera moves per key press.
+const PAN_STEP: i16 = 4;
+/// File the world is saved to if no other one was loaded.
+pub const DEFAULT_SAVE_PATH: &str = "world.sandbox";
+
 impl Default for App {
     fn default() -> Self {
-        Self {
-            running: true,
-            simulation_paused: false,
-            events: EventHandler::new(),
-            color: Color::White,
-            simulation_widget: Simulation::default(),
-        }
+        Self::with_simulation(Simulation::default())
     }
 }
 
@@ -38,14 +69,45 @@ impl App {
         Self::default()
     }
 
+    /// Constructs a new instance of [`App`] around an existing simulation.
+    pub fn with_simulation(simulation_widget: Simulation) -> Self {
+        let mut app = Self {
+            running: true,
+            simulation_paused: false,
+            speed: Speed::default(),
+            events: EventHandler::new(),
+            color: Color::White,
+            simulation_widget,
+            camera: Vec2::default(),
+            palette_area: Rect::default(),
+            tick_rate: RateCounter::default(),
+            frame_rate: RateCounter::default(),
+            tick_time: DurationAverage::default(),
+            save_path: PathBuf::from(DEFAULT_SAVE_PATH),
+            image_scale: 1,
+            recorder: None,
+            record_every: DEFAULT_EVERY,
+            max_frames: DEFAULT_MAX_FRAMES,
+            message: None,
+        };
+        app.update_color();
+        app
+    }
+
     /// Run the application's main loop.
     pub fn run(mut self, mut terminal: DefaultTerminal) -> color_eyre::Result<()> {
         while self.running {
             terminal.draw(|frame| {
                 let area = frame.area();
-                self.simulation_widget.update_window_size((&area).into());
+                let [simulation_area, palette_area, _] = App::layout(area);
+                self.simulation_widget
+                    .update_window_size((&simulation_area).into());
+                self.palette_area = palette_area;
+                // the window may have grown beyond the edge of the world
+                self.camera = self.simulation_widget.clamp_camera(&self.camera);
                 frame.render_widget(&self, area)
             })?;
+            self.frame_rate.record(Instant::now());
             self.handle_events()?;
         }
         Ok(())
@@ -58,7 +120,9 @@ impl App {
             }
             Event::Crossterm(event) => match event {
                 crossterm::event::Event::Key(key_event) => {
+                    self.message = None;
                     self.simulation_widget.handle_keyboard_event(&key_event)?;
+                    self.update_color();
                     match key_event.code {
                         KeyCode::Esc | KeyCode::Char('q') => self.events.send(AppEvent::Quit),
                         KeyCode::Char('c' | 'C')
@@ -66,15 +130,40 @@ impl App {
                         {
                             self.events.send(AppEvent::Quit)
                         }
-                        KeyCode::Enter => {
-                            self.simulation_paused = !self.simulation_paused;
-                        }
+                        KeyCode::Enter => self.events.send(AppEvent::TogglePause),
+                        KeyCode::Char('.') => self.events.send(AppEvent::Step),
+                        KeyCode::Char('+' | '=') => self.events.send(AppEvent::SpeedUp),
+                        KeyCode::Char('-') => self.events.send(AppEvent::SlowDown),
+                        KeyCode::Up | KeyCode::Char('w') => self.pan(Direction::Up),
+                        KeyCode::Left | KeyCode::Char('a') => self.pan(Direction::Left),
+                        KeyCode::Down | KeyCode::Char('s') => self.pan(Direction::Down),
+                        KeyCode::Right | KeyCode::Char('d') => self.pan(Direction::Right),
+                        KeyCode::F(5) => self.save(),
+                        KeyCode::F(6) => self.export_level(),
+                        KeyCode::F(9) => self.load(),
+                        KeyCode::Char('p') => self.export_image(),
+                        KeyCode::Char('r') => self.toggle_recording(),
                         // Other handlers you could add here.
                         _ => {}
                     }
                 }
                 crossterm::event::Event::Mouse(mouse_event) => {
-                    self.simulation_widget.handle_mouse_event(&mouse_event)?;
+                    let position = Position::new(mouse_event.column, mouse_event.row);
+                    let in_simulation = self
+                        .simulation_widget
+                        .window()
+                        .is_some_and(|window| window.is_inside(position.x, position.y));
+                    if self.palette_area.contains(position) {
+                        if mouse_event.kind == MouseEventKind::Down(MouseButton::Left) {
+                            self.select_at(position);
+                        }
+                    } else if in_simulation {
+                        self.simulation_widget
+                            .handle_mouse_event(&mouse_event, &self.camera)?;
+                    } else if let MouseEventKind::Up(_) = mouse_event.kind {
+                        // a stroke that ended over the status bar must not continue later on
+                        self.simulation_widget.brush_mut().lift();
+                    }
                 }
                 _ => {}
             },
@@ -82,6 +171,19 @@ impl App {
                 self.simulation_widget.handle_app_event(&app_event)?;
                 match app_event {
                     AppEvent::Quit => self.quit(),
+                    AppEvent::TogglePause => self.simulation_paused = !self.simulation_paused,
+                    AppEvent::Step => {
+                        self.simulation_paused = true;
+                        self.step()?;
+                    }
+                    AppEvent::SpeedUp => {
+                        self.speed.faster();
+                        self.events.set_tick_rate(self.speed.tick_rate());
+                    }
+                    AppEvent::SlowDown => {
+                        self.speed.slower();
+                        self.events.set_tick_rate(self.speed.tick_rate());
+                    }
                 }
             }
         }
@@ -95,8 +197,124 @@ impl App {
         if self.simulation_paused {
             return Ok(());
         }
-        self.simulation_widget.handle_ticks()
+        for _ in 0..self.speed.steps_per_tick() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Advances the simulation by a single step and measures how long it took.
+    pub fn step(&mut self) -> eyre::Result<()> {
+        let start = Instant::now();
+        self.simulation_widget.handle_ticks()?;
+        let end = Instant::now();
+        self.tick_time.record(end - start);
+        self.tick_rate.record(end);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.step(&self.simulation_widget);
+            if recorder.is_full() {
+                self.toggle_recording();
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the world to [`App::save_path`] and reports the outcome in the status bar.
+    pub fn save(&mut self) {
+        let path = self.save_path.display();
+        self.message = Some(match self.simulation_widget.save_file(&self.save_path) {
+            Ok(()) => format!("saved {path}"),
+            Err(error) => format!("{error:#}"),
+        });
+    }
+
+    /// Replaces the world with the one saved at [`App::save_path`] and reports the outcome in the
+    /// status bar.
+    pub fn load(&mut self) {
+        let path = self.save_path.display();
+        self.message = Some(match self.simulation_widget.load_file(&self.save_path) {
+            Ok(()) => format!("loaded {path}"),
+            Err(error) => format!("{error:#}"),
+        });
+        self.camera = self.simulation_widget.clamp_camera(&self.camera);
+    }
+
+    /// Writes the world as a text level next to [`App::save_path`], with the extension `txt`.
+    pub fn export_level(&mut self) {
+        let path = self.save_path.with_extension("txt");
+        let result = self.simulation_widget.level().and_then(|level| {
+            let text = level.to_text(self.simulation_widget.materials());
+            Ok(fs::write(&path, text)?)
+        });
+        self.message = Some(match result {
+            Ok(()) => format!("exported {}", path.display()),
+            Err(error) => format!("failed to export {}: {error}", path.display()),
+        });
+    }
+
+    /// Saves a picture of the world next to [`App::save_path`], as PNG if this build supports it.
+    pub fn export_image(&mut self) {
+        let extension = if cfg!(feature = "png") { "png" } else { "ppm" };
+        let path = self.save_path.with_extension(extension);
+        let image = self.simulation_widget.snapshot().scaled(self.image_scale);
+        self.message = Some(match image.save(&path) {
+            Ok(()) => format!("exported {}", path.display()),
+            Err(error) => format!("{error:#}"),
+        });
+    }
+
+    /// Starts a timelapse, or stops the running one and saves it next to [`App::save_path`]: as
+    /// GIF if this build supports it, as numbered PPM frames in a directory otherwise.
+    pub fn toggle_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            let mut recorder = Recorder::new(self.record_every, self.max_frames);
+            recorder.capture(&self.simulation_widget);
+            self.recorder = Some(recorder);
+            return;
+        };
+        let path = if cfg!(feature = "gif") {
+            self.save_path.with_extension("gif")
+        } else {
+            let stem = self
+                .save_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            self.save_path.with_file_name(format!("{stem}-frames"))
+        };
+        self.message = Some(match recorder.save(&path, self.image_scale) {
+            Ok(()) => format!(
+                "recorded {} frames to {}",
+                recorder.frames().len(),
+                path.display()
+            ),
+            Err(error) => format!("{error:#}"),
+        });
+    }
+
+    /// Selects the material listed at `position` of the palette.
+    pub fn select_at(&mut self, position: Position) {
+        let materials = self.simulation_widget.materials();
+        if let Some(material) = Palette::material_at(materials, self.palette_area, position) {
+            self.simulation_widget.select(material);
+            self.update_color();
+        }
     }
+
+    /// Takes on the colour of the selected material.
+    fn update_color(&mut self) {
+        if let Some(material) = self.simulation_widget.selected() {
+            let (r, g, b) = self.simulation_widget.materials().get(material).color;
+            self.color = Color::Rgb(r, g, b);
+        }
+    }
+
+    /// Moves the camera by [`PAN_STEP`] cells, but never beyond the edge of the world.
+    pub fn pan(&mut self, direction: Direction) {
+        let camera = &self.camera + Vec2::from(direction) * PAN_STEP;
+        self.camera = self.simulation_widget.clamp_camera(&camera);
+    }
+
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
         self.running = false;
@@ -0,0 +1,306 @@
+use std::fmt::Debug;
+
+use color_eyre::eyre;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    chunk::MAX_REACH,
+    coord::{Direction, Vec2},
+    grid::Neighbourhood,
+    particle::{Action, Cell, SimulationRng},
+};
+
+/// How the particles of a material move and evolve.
+///
+/// Every material is composed with one behaviour. The presets below cover the built-in families
+/// and are configured from the material file, other behaviours can be attached to a material with
+/// [`MaterialRegistry::set_behaviour`](crate::material::MaterialRegistry::set_behaviour).
+///
+/// A behaviour may look at and move a particle at most [`MAX_REACH`] cells away in a single tick,
+/// `Action::Move(_, n)` with a larger `n` would reach into chunks that are updated at the same
+/// time in parallel builds.
+pub trait MaterialBehaviour: Debug + Send + Sync {
+    /// Decides what `cell` does this tick, given its surroundings.
+    fn update(
+        &self,
+        cell: &Cell,
+        neighbours: &Neighbourhood,
+        rng: &mut SimulationRng,
+    ) -> eyre::Result<Action>;
+
+    /// Whether particles with this behaviour are burning, flammable materials catch fire next to
+    /// them.
+    fn burns(&self) -> bool {
+        false
+    }
+}
+
+/// The behaviour presets that can be picked in the material file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviourKind {
+    Static,
+    Powder,
+    Liquid,
+    Fire,
+    Gas,
+    Life,
+}
+
+/// Never moves.
+#[derive(Clone, Copy, Debug)]
+pub struct Static;
+
+impl MaterialBehaviour for Static {
+    fn update(
+        &self,
+        _cell: &Cell,
+        _neighbours: &Neighbourhood,
+        _rng: &mut SimulationRng,
+    ) -> eyre::Result<Action> {
+        Ok(Action::None)
+    }
+}
+
+/// Falls down and piles up.
+#[derive(Clone, Copy, Debug)]
+pub struct Powder {
+    /// Chance per tick to slide off diagonally when resting on something, low values pile up into
+    /// steep heaps.
+    pub slip: f32,
+}
+
+impl Default for Powder {
+    fn default() -> Self {
+        Self { slip: 1.0 }
+    }
+}
+
+impl MaterialBehaviour for Powder {
+    fn update(
+        &self,
+        cell: &Cell,
+        neighbours: &Neighbourhood,
+        rng: &mut SimulationRng,
+    ) -> eyre::Result<Action> {
+        if let Some(action) = cell.try_move(neighbours, Direction::Down) {
+            return Ok(action);
+        }
+        if !rng.random_bool(self.slip as f64) {
+            return Ok(Action::Wait);
+        }
+
+        let (first, second) = random_order(rng, Direction::DownRight, Direction::DownLeft);
+        if let Some(action) = cell.try_move(neighbours, first) {
+            return Ok(action);
+        };
+        if let Some(action) = cell.try_move(neighbours, second) {
+            return Ok(action);
+        }
+        Ok(Action::None)
+    }
+}
+
+/// Falls down and spreads sideways.
+#[derive(Clone, Copy, Debug)]
+pub struct Liquid {
+    /// Number of cells the liquid may travel sideways per tick.
+    pub dispersion: u16,
+    /// Chance per tick to not move at all, thick liquids like honey level out slowly.
+    pub viscosity: f32,
+}
+
+impl Default for Liquid {
+    fn default() -> Self {
+        Self {
+            dispersion: 1,
+            viscosity: 0.0,
+        }
+    }
+}
+
+impl MaterialBehaviour for Liquid {
+    fn update(
+        &self,
+        cell: &Cell,
+        neighbours: &Neighbourhood,
+        rng: &mut SimulationRng,
+    ) -> eyre::Result<Action> {
+        if self.viscosity > 0.0 && rng.random_bool(self.viscosity as f64) {
+            return Ok(Action::Wait);
+        }
+        flow(
+            cell,
+            neighbours,
+            Direction::Down,
+            (Direction::DownRight, Direction::DownLeft),
+            self.dispersion,
+            rng,
+        )
+    }
+}
+
+/// Rises up and spreads sideways.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Gas;
+
+impl MaterialBehaviour for Gas {
+    fn update(
+        &self,
+        cell: &Cell,
+        neighbours: &Neighbourhood,
+        rng: &mut SimulationRng,
+    ) -> eyre::Result<Action> {
+        flow(
+            cell,
+            neighbours,
+            Direction::Up,
+            (Direction::UpRight, Direction::UpLeft),
+            1,
+            rng,
+        )
+    }
+}
+
+/// Stays in place and sets flammable neighbours alight, it only burns out once its lifetime is
+/// over.
+#[derive(Clone, Copy, Debug)]
+pub struct Fire;
+
+impl MaterialBehaviour for Fire {
+    fn update(
+        &self,
+        _cell: &Cell,
+        _neighbours: &Neighbourhood,
+        _rng: &mut SimulationRng,
+    ) -> eyre::Result<Action> {
+        Ok(Action::None)
+    }
+
+    fn burns(&self) -> bool {
+        true
+    }
+}
+
+/// Grows into empty neighbouring cells and dies off when it is too lonely or too crowded, like the
+/// cells of a cellular automaton.
+#[derive(Clone, Copy, Debug)]
+pub struct Life {
+    /// Chance per tick to grow into one of the empty neighbouring cells.
+    pub growth: f32,
+    /// Inclusive range of neighbours of the same material a particle survives with.
+    pub survive: (u8, u8),
+}
+
+impl Default for Life {
+    fn default() -> Self {
+        Self {
+            growth: 0.05,
+            survive: (0, 4),
+        }
+    }
+}
+
+impl MaterialBehaviour for Life {
+    fn update(
+        &self,
+        cell: &Cell,
+        neighbours: &Neighbourhood,
+        rng: &mut SimulationRng,
+    ) -> eyre::Result<Action> {
+        let kin = neighbours
+            .iter()
+            .filter(|(_, other)| other.material == cell.material)
+            .count();
+        if !(self.survive.0 as usize..=self.survive.1 as usize).contains(&kin) {
+            return Ok(Action::Vanish);
+        }
+        let free: Vec<Direction> = Direction::ALL
+            .into_iter()
+            .filter(|direction| neighbours.is_empty(*direction))
+            .collect();
+        if free.is_empty() {
+            return Ok(Action::None);
+        }
+        if !rng.random_bool(self.growth as f64) {
+            return Ok(Action::Wait);
+        }
+        Ok(Action::Grow(free[rng.random_range(0..free.len())]))
+    }
+}
+
+/// Shared movement of liquids and gases: flows towards `flow`, liquids pass `Down` and gases `Up`,
+/// and spreads up to `dispersion` cells sideways once it cannot get any further.
+fn flow(
+    cell: &Cell,
+    neighbours: &Neighbourhood,
+    flow: Direction,
+    diagonals: (Direction, Direction),
+    dispersion: u16,
+    rng: &mut SimulationRng,
+) -> eyre::Result<Action> {
+    if let Some(action) = cell.try_move(neighbours, flow) {
+        return Ok(action);
+    }
+
+    let (first, second) = random_order(rng, diagonals.0, diagonals.1);
+    if let Some(action) = cell.try_move(neighbours, first) {
+        return Ok(action);
+    };
+    if let Some(action) = cell.try_move(neighbours, second) {
+        return Ok(action);
+    }
+
+    let (first, second) = random_order(rng, Direction::Right, Direction::Left);
+    for direction in [first, second] {
+        match cell.try_move(neighbours, direction) {
+            Some(Action::Move(direction, _)) => {
+                let mut distance = spread(neighbours, direction, flow, dispersion);
+                // particles sliding all the way up to each other keep bouncing back and forth in
+                // step, stopping short at random lets them settle
+                let ahead = Vec2::from(direction) * (distance as i16 + 1);
+                if distance < MAX_REACH as u16
+                    && neighbours
+                        .at(ahead)
+                        .is_some_and(|other| other.material == cell.material)
+                {
+                    distance = rng.random_range(1..=distance);
+                }
+                return Ok(Action::Move(direction, distance));
+            }
+            Some(action) => return Ok(action),
+            None => {}
+        }
+    }
+    Ok(Action::None)
+}
+
+/// Number of empty cells, up to `dispersion`, a fluid passes towards `direction` before it hits an
+/// obstacle. It stops early at a gap it can flow into.
+fn spread(
+    neighbours: &Neighbourhood,
+    direction: Direction,
+    flow: Direction,
+    dispersion: u16,
+) -> u16 {
+    let (step, onward) = (Vec2::from(direction), Vec2::from(flow));
+    let mut distance = 0;
+    let mut offset = Vec2::from((0i16, 0i16));
+    while distance < dispersion.max(1) {
+        offset = &offset + &step;
+        if neighbours.at(offset.clone()).is_some() {
+            break;
+        }
+        distance += 1;
+        if neighbours.at(&offset + &onward).is_none() {
+            break;
+        }
+    }
+    distance
+}
+
+/// Returns both directions in random order, so particles don't drift to one side.
+fn random_order(rng: &mut SimulationRng, a: Direction, b: Direction) -> (Direction, Direction) {
+    if rng.random_bool(0.5) { (a, b) } else { (b, a) }
+}
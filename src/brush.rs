@@ -0,0 +1,215 @@
+use rand::Rng;
+
+use crate::{coord::Vec2, particle::SimulationRng};
+
+/// Largest radius a brush can have.
+pub const MAX_RADIUS: u16 = 16;
+/// Share of the cells under a spray brush that get painted per dab.
+const SPRAY_DENSITY: f64 = 0.2;
+
+/// The outline of the cells a brush covers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BrushShape {
+    #[default]
+    Circle,
+    Square,
+    /// A circle of which only a random share of the cells gets painted.
+    Spray,
+}
+
+impl BrushShape {
+    /// The shape after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            BrushShape::Circle => BrushShape::Square,
+            BrushShape::Square => BrushShape::Spray,
+            BrushShape::Spray => BrushShape::Circle,
+        }
+    }
+}
+
+/// Paints or erases the cells under the mouse.
+///
+/// A brush remembers where the last stroke ended, so a fast drag paints a continuous line instead
+/// of a trail of dots.
+#[derive(Clone, Debug, Default)]
+pub struct Brush {
+    /// Distance from the centre to the edge, 0 paints a single cell.
+    radius: u16,
+    shape: BrushShape,
+    /// Whether the brush removes particles instead of placing them.
+    eraser: bool,
+    /// End of the current stroke, `None` while the brush is lifted.
+    last: Option<Vec2>,
+}
+
+impl Brush {
+    pub fn radius(&self) -> u16 {
+        self.radius
+    }
+
+    /// Changes the radius, up to [`MAX_RADIUS`].
+    pub fn set_radius(&mut self, radius: u16) {
+        self.radius = radius.min(MAX_RADIUS);
+    }
+
+    pub fn grow(&mut self) {
+        self.set_radius(self.radius.saturating_add(1));
+    }
+
+    pub fn shrink(&mut self) {
+        self.set_radius(self.radius.saturating_sub(1));
+    }
+
+    pub fn shape(&self) -> BrushShape {
+        self.shape
+    }
+
+    pub fn set_shape(&mut self, shape: BrushShape) {
+        self.shape = shape;
+    }
+
+    pub fn is_eraser(&self) -> bool {
+        self.eraser
+    }
+
+    pub fn set_eraser(&mut self, eraser: bool) {
+        self.eraser = eraser;
+    }
+
+    /// Ends the current stroke, the next one starts over at wherever it is put down.
+    pub fn lift(&mut self) {
+        self.last = None;
+    }
+
+    /// Continues the current stroke to `to` and returns the centres of all dabs along the way. A
+    /// new stroke starts with a single dab at `to`.
+    pub fn stroke(&mut self, to: Vec2) -> Vec<Vec2> {
+        let centres = match self.last.take() {
+            // the start was painted by the previous part of the stroke
+            Some(from) => line(&from, &to).into_iter().skip(1).collect(),
+            None => vec![to.clone()],
+        };
+        self.last = Some(to);
+        centres
+    }
+
+    /// The cells a single dab centred on `centre` covers.
+    pub fn footprint(&self, centre: &Vec2, rng: &mut SimulationRng) -> Vec<Vec2> {
+        let radius = self.radius as i16;
+        // the extra radius rounds off the pointy tips of small circles
+        let inside = |dx: i16, dy: i16| match self.shape {
+            BrushShape::Square => true,
+            BrushShape::Circle | BrushShape::Spray => dx * dx + dy * dy <= radius * radius + radius,
+        };
+        let mut cells = vec![];
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if !inside(dx, dy) {
+                    continue;
+                }
+                if self.shape == BrushShape::Spray && radius > 0 && !rng.random_bool(SPRAY_DENSITY)
+                {
+                    continue;
+                }
+                // cells that don't even have a position are outside of any world
+                if let (Some(x), Some(y)) = (centre.x.checked_add(dx), centre.y.checked_add(dy)) {
+                    cells.push(Vec2 { x, y });
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// All cells on the straight line from `from` to `to`, both included, using Bresenham's algorithm.
+pub fn line(from: &Vec2, to: &Vec2) -> Vec<Vec2> {
+    let (dx, dy) = ((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let (step_x, step_y) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+    let mut error = dx + dy;
+    let mut pos = from.clone();
+    let mut cells = vec![pos.clone()];
+    while pos != *to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            pos.x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            pos.y += step_y;
+        }
+        cells.push(pos.clone());
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn footprint(shape: BrushShape, radius: u16) -> Vec<Vec2> {
+        let mut brush = Brush::default();
+        brush.set_shape(shape);
+        brush.set_radius(radius);
+        brush.footprint(&(10i16, 10).into(), &mut SimulationRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn test_line_covers_every_step() {
+        let cells: Vec<(i16, i16)> = line(&(0i16, 0).into(), &(5i16, 2).into())
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        assert_eq!(cells, vec![(0, 0), (1, 0), (2, 1), (3, 1), (4, 2), (5, 2)]);
+    }
+
+    #[test]
+    fn test_stroke_continues_from_last_position() {
+        let mut brush = Brush::default();
+
+        assert_eq!(brush.stroke((0i16, 0).into()).len(), 1);
+        let centres = brush.stroke((0i16, 4).into());
+        assert_eq!(centres.first(), Some(&(0i16, 1).into()));
+        assert_eq!(centres.len(), 4);
+
+        brush.lift();
+        assert_eq!(brush.stroke((9i16, 9).into()), vec![(9i16, 9).into()]);
+    }
+
+    #[test]
+    fn test_footprint_shapes() {
+        assert_eq!(footprint(BrushShape::Circle, 0), vec![(10i16, 10).into()]);
+        assert_eq!(footprint(BrushShape::Square, 2).len(), 25);
+        assert_eq!(footprint(BrushShape::Circle, 2).len(), 21);
+        assert!(footprint(BrushShape::Spray, 2).len() < 21);
+    }
+
+    #[test]
+    fn test_footprint_skips_cells_beyond_coordinates() {
+        let mut brush = Brush::default();
+        brush.set_shape(BrushShape::Square);
+        brush.set_radius(2);
+
+        let cells = brush.footprint(
+            &(i16::MAX - 1, i16::MIN).into(),
+            &mut SimulationRng::seed_from_u64(0),
+        );
+
+        assert_eq!(cells.len(), 4 * 3);
+    }
+
+    #[test]
+    fn test_radius_is_limited() {
+        let mut brush = Brush::default();
+        brush.shrink();
+        assert_eq!(brush.radius(), 0);
+
+        brush.set_radius(100);
+        brush.grow();
+        assert_eq!(brush.radius(), MAX_RADIUS);
+    }
+}
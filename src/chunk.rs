@@ -0,0 +1,299 @@
+use crate::coord::Vec2;
+
+/// Width and height of a chunk in cells.
+pub const CHUNK_SIZE: u16 = 16;
+
+/// Farthest a particle may move or look in a single tick, in cells. Chunks that are updated at the
+/// same time are a whole chunk apart, so they never reach into each other.
+pub const MAX_REACH: i16 = CHUNK_SIZE as i16 / 2;
+
+/// Inclusive rectangle of cells that may change, in grid coordinates.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DirtyRect {
+    pub min_x: i16,
+    pub min_y: i16,
+    pub max_x: i16,
+    pub max_y: i16,
+}
+
+impl DirtyRect {
+    fn new(pos: &Vec2) -> Self {
+        Self {
+            min_x: pos.x,
+            min_y: pos.y,
+            max_x: pos.x,
+            max_y: pos.y,
+        }
+    }
+
+    fn extend(&mut self, pos: &Vec2) {
+        self.min_x = self.min_x.min(pos.x);
+        self.min_y = self.min_y.min(pos.y);
+        self.max_x = self.max_x.max(pos.x);
+        self.max_y = self.max_y.max(pos.y);
+    }
+
+    pub fn contains(&self, pos: &Vec2) -> bool {
+        (self.min_x..=self.max_x).contains(&pos.x) && (self.min_y..=self.max_y).contains(&pos.y)
+    }
+
+    /// Number of cells covered.
+    pub fn area(&self) -> usize {
+        (self.max_x - self.min_x + 1) as usize * (self.max_y - self.min_y + 1) as usize
+    }
+}
+
+/// Bookkeeping of a square block of [`CHUNK_SIZE`] cells.
+#[derive(Clone, Debug, Default)]
+pub struct Chunk {
+    /// Cells to visit in the current tick, `None` while the chunk sleeps.
+    dirty: Option<DirtyRect>,
+    /// Cells woken up for the next tick.
+    next: Option<DirtyRect>,
+    /// Number of cells visited in the current tick.
+    updated: usize,
+}
+
+impl Chunk {
+    pub fn is_awake(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// The cells visited in the current tick, `None` while the chunk sleeps.
+    pub fn dirty(&self) -> Option<DirtyRect> {
+        self.dirty
+    }
+
+    /// Number of cells visited in the current tick.
+    pub fn updated(&self) -> usize {
+        self.updated
+    }
+}
+
+/// Summary of how much work the chunks saved in the current tick.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ChunkStats {
+    pub chunks: usize,
+    pub awake: usize,
+    /// Cells inside of the dirty rectangles of awake chunks.
+    pub dirty_cells: usize,
+    /// Cells that were visited.
+    pub updated_cells: usize,
+    /// Cells of the whole grid, all of which would be visited without chunks.
+    pub total_cells: usize,
+}
+
+/// Splits the grid into chunks and tracks which of them need to be updated.
+///
+/// Anything that changes a cell wakes the area around it for the next tick. A chunk nobody woke
+/// sleeps and is skipped entirely, so settled regions cost nothing.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMap {
+    /// Extent of the grid in cells.
+    width: u16,
+    height: u16,
+    /// Extent in chunks.
+    columns: u16,
+    rows: u16,
+    chunks: Vec<Chunk>,
+}
+
+impl ChunkMap {
+    /// Constructs the chunks of a `width` x `height` grid, all of them awake for the next tick.
+    pub fn new(width: u16, height: u16) -> Self {
+        let (columns, rows) = (width.div_ceil(CHUNK_SIZE), height.div_ceil(CHUNK_SIZE));
+        let mut map = Self {
+            width,
+            height,
+            columns,
+            rows,
+            chunks: vec![Chunk::default(); columns as usize * rows as usize],
+        };
+        map.wake_all();
+        map
+    }
+
+    /// Extent in chunks.
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    fn index(&self, pos: &Vec2) -> Option<usize> {
+        if pos.x < 0 || pos.y < 0 || pos.x as u16 >= self.width || pos.y as u16 >= self.height {
+            return None;
+        }
+        let (column, row) = (pos.x as u16 / CHUNK_SIZE, pos.y as u16 / CHUNK_SIZE);
+        Some(row as usize * self.columns as usize + column as usize)
+    }
+
+    /// The chunk at `column` and `row`.
+    pub fn get(&self, column: u16, row: u16) -> Option<&Chunk> {
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        self.chunks
+            .get(row as usize * self.columns as usize + column as usize)
+    }
+
+    /// Wakes `pos` and its eight neighbours for the next tick, even if they belong to another
+    /// chunk.
+    pub fn wake(&mut self, pos: &Vec2) {
+        for y in pos.y - 1..=pos.y + 1 {
+            for x in pos.x - 1..=pos.x + 1 {
+                let pos: Vec2 = (x, y).into();
+                if let Some(idx) = self.index(&pos) {
+                    let next = &mut self.chunks[idx].next;
+                    match next {
+                        Some(rect) => rect.extend(&pos),
+                        None => *next = Some(DirtyRect::new(&pos)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wakes every cell for the next tick.
+    pub fn wake_all(&mut self) {
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let bounds = self.bounds(column, row);
+                self.chunks[row as usize * self.columns as usize + column as usize].next =
+                    Some(bounds);
+            }
+        }
+    }
+
+    /// All cells of the chunk at `column` and `row`, chunks at the edge may be cut off by the
+    /// grid.
+    pub fn bounds(&self, column: u16, row: u16) -> DirtyRect {
+        let (x, y) = (column * CHUNK_SIZE, row * CHUNK_SIZE);
+        DirtyRect {
+            min_x: x as i16,
+            min_y: y as i16,
+            max_x: (x + CHUNK_SIZE).min(self.width) as i16 - 1,
+            max_y: (y + CHUNK_SIZE).min(self.height) as i16 - 1,
+        }
+    }
+
+    /// Starts a new tick: everything woken so far becomes the area to update.
+    pub fn begin_tick(&mut self) {
+        for chunk in &mut self.chunks {
+            chunk.dirty = chunk.next.take();
+            chunk.updated = 0;
+        }
+    }
+
+    /// Whether the chunk containing `pos` is updated in the current tick.
+    pub fn is_awake(&self, pos: &Vec2) -> bool {
+        self.index(pos)
+            .is_some_and(|idx| self.chunks[idx].is_awake())
+    }
+
+    /// Counts `count` visits to cells of the chunk containing `pos`.
+    pub fn record_updates(&mut self, pos: &Vec2, count: usize) {
+        if let Some(idx) = self.index(pos) {
+            self.chunks[idx].updated += count;
+        }
+    }
+
+    /// Dirty rectangles of the awake chunks whose column and row have the parity of `phase`. None
+    /// of them are neighbours, so they can be updated independently.
+    pub fn awake_in_phase(&self, phase: (u16, u16)) -> Vec<DirtyRect> {
+        self.iter()
+            .filter(|(column, row, _)| (column % 2, row % 2) == phase)
+            .filter_map(|(_, _, chunk)| chunk.dirty)
+            .collect()
+    }
+
+    /// Dirty rectangles of the awake chunks overlapping row `y`, ordered by column.
+    pub fn dirty_in_row(&self, y: i16) -> impl DoubleEndedIterator<Item = DirtyRect> + '_ {
+        let row = (y as u16 / CHUNK_SIZE) as usize * self.columns as usize;
+        self.chunks[row..row + self.columns as usize]
+            .iter()
+            .filter_map(move |chunk| {
+                chunk
+                    .dirty
+                    .filter(|rect| (rect.min_y..=rect.max_y).contains(&y))
+            })
+    }
+
+    /// Iterates over all chunks with their column and row.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u16, &Chunk)> + '_ {
+        self.chunks.iter().enumerate().map(|(idx, chunk)| {
+            (
+                (idx % self.columns as usize) as u16,
+                (idx / self.columns as usize) as u16,
+                chunk,
+            )
+        })
+    }
+
+    pub fn stats(&self) -> ChunkStats {
+        let awake = self.chunks.iter().filter_map(|chunk| chunk.dirty);
+        ChunkStats {
+            chunks: self.chunks.len(),
+            awake: awake.clone().count(),
+            dirty_cells: awake.map(|rect| rect.area()).sum(),
+            updated_cells: self.chunks.iter().map(|chunk| chunk.updated).sum(),
+            total_cells: self.width as usize * self.height as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_chunks_are_awake() {
+        let mut chunks = ChunkMap::new(40, 20);
+        chunks.begin_tick();
+
+        let stats = chunks.stats();
+        assert_eq!(stats.chunks, 6);
+        assert_eq!(stats.awake, 6);
+        assert_eq!(stats.dirty_cells, 40 * 20);
+    }
+
+    #[test]
+    fn test_chunks_nobody_woke_sleep() {
+        let mut chunks = ChunkMap::new(40, 20);
+        chunks.begin_tick();
+        chunks.begin_tick();
+
+        assert_eq!(chunks.stats().awake, 0);
+        assert!(!chunks.is_awake(&(0i16, 0).into()));
+    }
+
+    #[test]
+    fn test_wake_reaches_across_chunk_borders() {
+        let mut chunks = ChunkMap::new(40, 20);
+        chunks.begin_tick();
+        chunks.wake(&(15i16, 15).into());
+        chunks.begin_tick();
+
+        assert_eq!(chunks.stats().awake, 4);
+        assert_eq!(
+            chunks.get(1, 1).and_then(Chunk::dirty),
+            Some(DirtyRect {
+                min_x: 16,
+                min_y: 16,
+                max_x: 16,
+                max_y: 16
+            })
+        );
+        assert_eq!(
+            chunks.get(0, 0).and_then(Chunk::dirty),
+            Some(DirtyRect {
+                min_x: 14,
+                min_y: 14,
+                max_x: 15,
+                max_y: 15
+            })
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::{
+    grid::{Boundaries, Boundary, MAX_EXTENT},
+    recorder::{DEFAULT_EVERY, DEFAULT_MAX_FRAMES},
+    simulation_widget::ResizePolicy,
+};
+
+/// Command line arguments.
+#[derive(Parser, Debug, Default)]
+#[command(version, about)]
+pub struct Cli {
+    /// Load the material definitions from this TOML file instead of the built-in ones.
+    #[arg(long, value_name = "FILE")]
+    pub materials: Option<PathBuf>,
+
+    /// Seed for the random decisions of the simulation, the same seed and input always play out the
+    /// same way. Picked at random if left out.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Fixed extent of the world as `<WIDTH>x<HEIGHT>` cells, pan with the arrow keys or WASD to
+    /// see all of it. Follows the size of the terminal if left out.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+    pub world: Option<(u16, u16)>,
+
+    /// How the world adapts when the terminal is resized. A fixed `--world` always scrolls.
+    #[arg(long, value_enum, default_value_t)]
+    pub resize: ResizePolicy,
+
+    /// What lies beyond the edges of the world: `wall`, `void` or `wrap`. Either one for all edges
+    /// or four separated by commas, in the order top, right, bottom and left. Press `b` to cycle
+    /// through them while running.
+    #[arg(long, value_name = "BOUNDARY", value_parser = parse_boundaries)]
+    pub boundary: Option<Boundaries>,
+
+    /// Start with the world saved in this file. F5 saves back to it and F9 loads it again, both
+    /// use `world.sandbox` if left out.
+    #[arg(long, value_name = "FILE")]
+    pub load: Option<PathBuf>,
+
+    /// Paint the text level in this file into the world at the start, see `src/level.rs` for the
+    /// format. The world takes on the size of the level unless `--world` or `--load` set one.
+    #[arg(long, value_name = "FILE")]
+    pub level: Option<PathBuf>,
+
+    /// Paint a PPM image, or PNG in builds with the `png` feature, into the world at the start.
+    /// Each pixel becomes the material with the closest colour, black pixels stay empty. The world
+    /// takes on the size of the image unless `--world`, `--load` or `--level` set one.
+    #[arg(long, value_name = "FILE")]
+    pub import: Option<PathBuf>,
+
+    /// Cell the upper left corner of the imported image lands on, as `<X>,<Y>`.
+    #[arg(long, value_name = "X,Y", default_value = "0,0", value_parser = parse_offset)]
+    pub import_offset: (i16, i16),
+
+    /// Factor the imported image is resized by before it is painted, like `0.5` or `2`.
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_factor)]
+    pub import_scale: f32,
+
+    /// Map the imported colour `RRGGBB` to a material, or to `empty`. Pixels take on the closest of
+    /// the given colours instead of the material colours if this is given at least once.
+    #[arg(long, value_name = "RRGGBB=MATERIAL", value_parser = parse_color_mapping)]
+    pub import_color: Vec<((u8, u8, u8), String)>,
+
+    /// Pixels per cell in exported images, press `p` to export one while running.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=64)
+    )]
+    pub scale: u32,
+
+    /// Ticks between two frames of a recording, press `r` to start and stop one while running.
+    #[arg(
+        long,
+        global = true,
+        value_name = "TICKS",
+        default_value_t = DEFAULT_EVERY,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub record_every: u32,
+
+    /// Frames after which a recording stops.
+    #[arg(long, global = true, value_name = "FRAMES", default_value_t = DEFAULT_MAX_FRAMES)]
+    pub max_frames: usize,
+
+    /// Run without a terminal instead of starting the interactive sandbox.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Things to do without a terminal, on the world set up by the other arguments.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Simulate a number of ticks and save a picture of the world as PPM, or PNG in builds with
+    /// the `png` feature. Needs a world from `--world`, `--load` or `--level`.
+    Export {
+        /// File to write, the format follows the extension.
+        output: PathBuf,
+        /// Ticks to simulate before the picture is taken.
+        #[arg(long, default_value_t = 0)]
+        ticks: u64,
+    },
+    /// Simulate a number of ticks and record them as an animated GIF in builds with the `gif`
+    /// feature, or as numbered PPM frames in a directory. Needs a world like `export`.
+    Record {
+        /// GIF file to write if it ends in `.gif`, directory for the frames otherwise.
+        output: PathBuf,
+        /// Ticks to simulate, the recording ends earlier once it has `--max-frames` frames.
+        #[arg(long)]
+        ticks: u64,
+    },
+}
+
+/// Parses the boundaries of all edges like `wall`, or of each edge like `wall,wall,void,wall`.
+fn parse_boundaries(value: &str) -> Result<Boundaries, String> {
+    let edges = value
+        .split(',')
+        .map(|edge| clap::ValueEnum::from_str(edge.trim(), true))
+        .collect::<Result<Vec<Boundary>, String>>()?;
+    match edges[..] {
+        [all] => Ok(Boundaries::all(all)),
+        [top, right, bottom, left] => Ok(Boundaries {
+            top,
+            right,
+            bottom,
+            left,
+        }),
+        _ => Err(format!(
+            "expected one boundary or four for top, right, bottom and left, got `{value}`"
+        )),
+    }
+}
+
+/// Parses a position like `10,-4`.
+fn parse_offset(value: &str) -> Result<(i16, i16), String> {
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected <X>,<Y>, got `{value}`"))?;
+    let parse = |number: &str| {
+        number
+            .trim()
+            .parse::<i16>()
+            .map_err(|_| format!("`{number}` is not a cell coordinate"))
+    };
+    Ok((parse(x)?, parse(y)?))
+}
+
+/// Parses a positive factor like `0.5`.
+fn parse_factor(value: &str) -> Result<f32, String> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|factor| factor.is_finite() && *factor > 0.0)
+        .ok_or_else(|| format!("`{value}` is not a positive factor"))
+}
+
+/// Parses a colour mapping like `ff8800=lava`, the leading `#` of the colour is optional.
+fn parse_color_mapping(value: &str) -> Result<((u8, u8, u8), String), String> {
+    let (color, material) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <RRGGBB>=<MATERIAL>, got `{value}`"))?;
+    let hex = color.trim().trim_start_matches('#');
+    let channel = |idx: usize| {
+        hex.get(idx..idx + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(((r, g, b), material.trim().to_string())),
+        _ => Err(format!("`{color}` is not a colour like `ff8800`")),
+    }
+}
+
+/// Parses an extent like `400x200`.
+fn parse_size(value: &str) -> Result<(u16, u16), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("expected <WIDTH>x<HEIGHT>, got `{value}`"))?;
+    let parse = |number: &str| {
+        number
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|number| (1..=MAX_EXTENT).contains(number))
+            .ok_or_else(|| format!("`{number}` is not a number of cells from 1 to {MAX_EXTENT}"))
+    };
+    Ok((parse(width)?, parse(height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_world_size_fits_coordinates() {
+        let cli = Cli::try_parse_from(["sandbox", "--world", "32751x10"]).unwrap();
+        assert_eq!(cli.world, Some((MAX_EXTENT, 10)));
+
+        for world in ["32752x10", "10x32767", "40000x10", "0x10"] {
+            assert!(Cli::try_parse_from(["sandbox", "--world", world]).is_err());
+        }
+    }
+}
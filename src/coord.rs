@@ -4,7 +4,7 @@ use std::{
 };
 
 use color_eyre::eyre;
-#[derive(Hash, PartialEq, Eq, Clone)]
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Direction {
     Up,
     UpRight,
@@ -16,7 +16,21 @@ pub enum Direction {
     DownLeft,
 }
 
-#[derive(Debug, Clone)]
+impl Direction {
+    /// All eight directions, clockwise starting at [`Direction::Down`].
+    pub const ALL: [Direction; 8] = [
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+        Direction::UpLeft,
+        Direction::Up,
+        Direction::UpRight,
+        Direction::Right,
+        Direction::DownRight,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct Vec2 {
     pub x: i16,
     pub y: i16,
@@ -6,8 +6,54 @@ use std::{
     time::{Duration, Instant},
 };
 
-/// The frequency at which tick events are emitted.
-const TICK_FPS: f64 = 30.0;
+/// The frequency at which tick events are emitted at normal speed.
+pub const TICK_FPS: f64 = 30.0;
+
+/// Selectable simulation speeds, relative to one step per tick at [`TICK_FPS`].
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// How fast the simulation runs.
+///
+/// Slower speeds emit fewer ticks, faster ones run several simulation steps per tick, so the
+/// terminal isn't redrawn more often than it can keep up with.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Speed {
+    /// Index into [`SPEEDS`].
+    level: usize,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Self { level: 2 }
+    }
+}
+
+impl Speed {
+    /// Multiple of the normal speed.
+    pub fn factor(&self) -> f64 {
+        SPEEDS[self.level]
+    }
+
+    /// Switches to the next faster speed, if there is one.
+    pub fn faster(&mut self) {
+        self.level = (self.level + 1).min(SPEEDS.len() - 1);
+    }
+
+    /// Switches to the next slower speed, if there is one.
+    pub fn slower(&mut self) {
+        self.level = self.level.saturating_sub(1);
+    }
+
+    /// Tick events per second.
+    pub fn tick_rate(&self) -> f64 {
+        TICK_FPS * self.factor().min(1.0)
+    }
+
+    /// Simulation steps per tick event.
+    pub fn steps_per_tick(&self) -> u32 {
+        self.factor().max(1.0) as u32
+    }
+}
 
 /// Representation of all possible events.
 #[derive(Clone, Debug)]
@@ -35,6 +81,14 @@ pub enum Event {
 pub enum AppEvent {
     /// Quit the application.
     Quit,
+    /// Pause or resume the simulation.
+    TogglePause,
+    /// Advance the paused simulation by a single step, pausing it first if it is running.
+    Step,
+    /// Run the simulation at the next faster [`Speed`].
+    SpeedUp,
+    /// Run the simulation at the next slower [`Speed`].
+    SlowDown,
 }
 
 /// Terminal event handler.
@@ -44,15 +98,28 @@ pub struct EventHandler {
     sender: mpsc::Sender<Event>,
     /// Event receiver channel.
     receiver: mpsc::Receiver<Event>,
+    /// Passes changes of the tick rate on to the event thread.
+    tick_rate: mpsc::Sender<f64>,
 }
 
 impl EventHandler {
     /// Constructs a new instance of [`EventHandler`] and spawns a new thread to handle events.
     pub fn new() -> Self {
         let (sender, receiver) = mpsc::channel();
-        let actor = EventThread::new(sender.clone());
+        let (tick_rate, tick_rate_receiver) = mpsc::channel();
+        let actor = EventThread::new(sender.clone(), tick_rate_receiver);
         thread::spawn(|| actor.run());
-        Self { sender, receiver }
+        Self {
+            sender,
+            receiver,
+            tick_rate,
+        }
+    }
+
+    /// Changes how many tick events are emitted per second, starting with the next one.
+    pub fn set_tick_rate(&self, ticks_per_second: f64) {
+        // the event thread only stops when the terminal fails, there are no ticks to adjust then
+        let _ = self.tick_rate.send(ticks_per_second);
     }
 
     /// Receives an event from the sender.
@@ -89,21 +156,27 @@ impl Default for EventHandler {
 struct EventThread {
     /// Event sender channel.
     sender: mpsc::Sender<Event>,
+    /// Receives changes of the tick rate, see [`EventHandler::set_tick_rate`].
+    tick_rate: mpsc::Receiver<f64>,
 }
 
 impl EventThread {
     /// Constructs a new instance of [`EventThread`].
-    fn new(sender: mpsc::Sender<Event>) -> Self {
-        Self { sender }
+    fn new(sender: mpsc::Sender<Event>, tick_rate: mpsc::Receiver<f64>) -> Self {
+        Self { sender, tick_rate }
     }
 
     /// Runs the event thread.
     ///
     /// This function emits tick events at a fixed rate and polls for crossterm events in between.
     fn run(self) -> color_eyre::Result<()> {
-        let tick_interval = Duration::from_secs_f64(1.0 / TICK_FPS);
+        let mut tick_interval = Duration::from_secs_f64(1.0 / TICK_FPS);
         let mut last_tick = Instant::now();
         loop {
+            // only the latest change of the tick rate counts
+            while let Ok(ticks_per_second) = self.tick_rate.try_recv() {
+                tick_interval = Duration::from_secs_f64(1.0 / ticks_per_second);
+            }
             // emit tick events at a fixed rate
             let timeout = tick_interval.saturating_sub(last_tick.elapsed());
             if timeout == Duration::ZERO {
@@ -125,3 +198,41 @@ impl EventThread {
         let _ = self.sender.send(event);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed_stays_within_limits() {
+        let mut speed = Speed::default();
+        for _ in 0..10 {
+            speed.slower();
+        }
+        assert_eq!(speed.factor(), 0.25);
+
+        for _ in 0..10 {
+            speed.faster();
+        }
+        assert_eq!(speed.factor(), 8.0);
+    }
+
+    #[test]
+    fn test_slow_speeds_tick_less_often() {
+        let mut speed = Speed::default();
+        speed.slower();
+
+        assert_eq!(speed.tick_rate(), TICK_FPS / 2.0);
+        assert_eq!(speed.steps_per_tick(), 1);
+    }
+
+    #[test]
+    fn test_fast_speeds_step_several_times_per_tick() {
+        let mut speed = Speed::default();
+        speed.faster();
+        speed.faster();
+
+        assert_eq!(speed.tick_rate(), TICK_FPS);
+        assert_eq!(speed.steps_per_tick(), 4);
+    }
+}
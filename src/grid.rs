@@ -0,0 +1,480 @@
+use color_eyre::eyre;
+
+use crate::{
+    chunk::{CHUNK_SIZE, MAX_REACH},
+    coord::{Direction, Vec2},
+    material::MaterialRegistry,
+    particle::{BORDER, Cell},
+};
+
+/// Largest width or height of a grid. Positions up to a chunk past its last cell must still fit
+/// into an `i16`, chunks, particles looking [`MAX_REACH`](crate::chunk::MAX_REACH) cells ahead and
+/// the [brush](crate::brush::MAX_RADIUS) all reach that far.
+pub const MAX_EXTENT: u16 = i16::MAX as u16 - CHUNK_SIZE;
+
+/// What lies beyond an edge of the grid.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum Boundary {
+    /// A solid wall made of [`BORDER`] cells.
+    #[default]
+    Wall,
+    /// Nothing at all, particles that leave the grid are gone for good.
+    Void,
+    /// The opposite edge, so particles leaving on one side come back on the other.
+    Wrap,
+}
+
+/// The [`Boundary`] of each edge of a grid.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Boundaries {
+    pub top: Boundary,
+    pub right: Boundary,
+    pub bottom: Boundary,
+    pub left: Boundary,
+}
+
+impl Boundaries {
+    /// The same boundary on every edge.
+    pub fn all(boundary: Boundary) -> Self {
+        Self {
+            top: boundary,
+            right: boundary,
+            bottom: boundary,
+            left: boundary,
+        }
+    }
+
+    /// Whether any edge wraps around.
+    pub fn wraps(&self) -> bool {
+        [self.top, self.right, self.bottom, self.left].contains(&Boundary::Wrap)
+    }
+}
+
+/// Dense, row-major storage for every cell of the simulation.
+///
+/// Positions outside of the grid are resolved through its [`Boundaries`] by [`Grid::find`], so
+/// particle handlers never have to care about the extent of the world.
+#[derive(Clone, Default)]
+pub struct Grid {
+    /// Position of the upper left cell, only regions cut out of a larger grid don't start at the
+    /// origin.
+    origin: Vec2,
+    width: u16,
+    height: u16,
+    cells: Vec<Option<Cell>>,
+    boundaries: Boundaries,
+}
+
+impl Grid {
+    /// Constructs an empty grid with the given extent.
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            origin: Vec2::default(),
+            width,
+            height,
+            cells: vec![None; width as usize * height as usize],
+            boundaries: Boundaries::default(),
+        }
+    }
+
+    /// Constructs an empty grid like [`Grid::new`], but returns an error instead of aborting if
+    /// there is not enough memory for it.
+    pub fn try_new(width: u16, height: u16) -> eyre::Result<Self> {
+        let count = width as usize * height as usize;
+        let mut cells = Vec::new();
+        cells
+            .try_reserve_exact(count)
+            .map_err(|_| eyre::eyre!("not enough memory for a {width}x{height} world"))?;
+        cells.resize(count, None);
+        Ok(Self {
+            origin: Vec2::default(),
+            width,
+            height,
+            cells,
+            boundaries: Boundaries::default(),
+        })
+    }
+
+    pub fn boundaries(&self) -> Boundaries {
+        self.boundaries
+    }
+
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.boundaries = boundaries;
+    }
+
+    /// Copies the cells of the `width` x `height` rectangle starting at `origin` into a grid of
+    /// their own, which keeps using the positions of this grid.
+    ///
+    /// Everything outside of the region counts as border, see [`Grid::find`], except for edges
+    /// shared with a void edge of this grid. Parts of the rectangle outside of this grid are left
+    /// out. Wrapping edges can't be cut apart like that and become walls.
+    pub fn region(&self, origin: Vec2, width: u16, height: u16) -> Self {
+        let min = Vec2 {
+            x: origin.x.max(self.origin.x),
+            y: origin.y.max(self.origin.y),
+        };
+        let max = Vec2 {
+            // regions reaching past the largest world are cut off rather than overflowing
+            x: origin
+                .x
+                .saturating_add(width as i16)
+                .min(self.origin.x + self.width as i16),
+            y: origin
+                .y
+                .saturating_add(height as i16)
+                .min(self.origin.y + self.height as i16),
+        };
+        let mut region = Self::new((max.x - min.x).max(0) as u16, (max.y - min.y).max(0) as u16);
+        region.origin = min.clone();
+        let inherit = |edge: Boundary, shared: bool| match edge {
+            Boundary::Void if shared => Boundary::Void,
+            _ => Boundary::Wall,
+        };
+        region.boundaries = Boundaries {
+            top: inherit(self.boundaries.top, min.y == self.origin.y),
+            right: inherit(
+                self.boundaries.right,
+                max.x == self.origin.x + self.width as i16,
+            ),
+            bottom: inherit(
+                self.boundaries.bottom,
+                max.y == self.origin.y + self.height as i16,
+            ),
+            left: inherit(self.boundaries.left, min.x == self.origin.x),
+        };
+        for (row, y) in (min.y..max.y).enumerate() {
+            let Some(from) = self.index(&Vec2 { x: min.x, y }) else {
+                continue;
+            };
+            let width = region.width as usize;
+            region.cells[row * width..(row + 1) * width]
+                .clone_from_slice(&self.cells[from..from + width]);
+        }
+        region
+    }
+
+    /// Writes all cells of `region` back, see [`Grid::region`].
+    pub fn paste(&mut self, region: &Grid) {
+        let width = region.width as usize;
+        for row in 0..region.height as usize {
+            let pos = region.position(row * width);
+            // regions never reach beyond the grid they were cut out of
+            if let Some(to) = self.index(&pos) {
+                self.cells[to..to + width]
+                    .clone_from_slice(&region.cells[row * width..(row + 1) * width]);
+            }
+        }
+    }
+
+    fn index_from(&self, origin: &Vec2, pos: &Vec2) -> Option<usize> {
+        let (x, y) = (pos.x - origin.x, pos.y - origin.y);
+        if x < 0 || y < 0 || x as u16 >= self.width || y as u16 >= self.height {
+            return None;
+        }
+        Some(y as usize * self.width as usize + x as usize)
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn contains(&self, pos: &Vec2) -> bool {
+        self.index(pos).is_some()
+    }
+
+    /// Row-major index of `pos`, or `None` if it lies outside of the grid.
+    pub fn index(&self, pos: &Vec2) -> Option<usize> {
+        self.index_from(&self.origin, pos)
+    }
+
+    fn position(&self, idx: usize) -> Vec2 {
+        let width = self.width as usize;
+        Vec2 {
+            x: self.origin.x + (idx % width) as i16,
+            y: self.origin.y + (idx / width) as i16,
+        }
+    }
+
+    /// Returns the cell at `pos`, or `None` if the position is empty or outside of the grid.
+    pub fn get(&self, pos: &Vec2) -> Option<&Cell> {
+        self.index(pos).and_then(|idx| self.cells[idx].as_ref())
+    }
+
+    pub fn get_mut(&mut self, pos: &Vec2) -> Option<&mut Cell> {
+        self.index(pos).and_then(|idx| self.cells[idx].as_mut())
+    }
+
+    /// Like [`Grid::get`] but resolves positions outside of the grid through its [`Boundaries`]:
+    /// walls are [`BORDER`], the void is empty and wrapping edges continue on the other side.
+    pub fn find(&self, pos: &Vec2) -> Option<&Cell> {
+        let pos = self.wrap(pos);
+        match self.index(&pos) {
+            Some(idx) => self.cells[idx].as_ref(),
+            None if self.is_void(&pos) => None,
+            None => Some(&BORDER),
+        }
+    }
+
+    /// Maps a position beyond a wrapping edge onto the opposite side of the grid, any other
+    /// position is returned as it is.
+    pub fn wrap(&self, pos: &Vec2) -> Vec2 {
+        let wrap = |value: i16, origin: i16, extent: u16, before: Boundary, after: Boundary| {
+            let offset = value - origin;
+            let wraps = (offset < 0 && before == Boundary::Wrap)
+                || (offset >= extent as i16 && after == Boundary::Wrap);
+            if wraps && extent > 0 {
+                origin + offset.rem_euclid(extent as i16)
+            } else {
+                value
+            }
+        };
+        let boundaries = &self.boundaries;
+        Vec2 {
+            x: wrap(
+                pos.x,
+                self.origin.x,
+                self.width,
+                boundaries.left,
+                boundaries.right,
+            ),
+            y: wrap(
+                pos.y,
+                self.origin.y,
+                self.height,
+                boundaries.top,
+                boundaries.bottom,
+            ),
+        }
+    }
+
+    /// Whether `pos` lies beyond a void edge, particles moving there disappear. A corner between a
+    /// void and a wall counts as wall.
+    pub fn is_void(&self, pos: &Vec2) -> bool {
+        let pos = self.wrap(pos);
+        let (x, y) = (pos.x - self.origin.x, pos.y - self.origin.y);
+        let crossed = [
+            (y < 0, self.boundaries.top),
+            (x >= self.width as i16, self.boundaries.right),
+            (y >= self.height as i16, self.boundaries.bottom),
+            (x < 0, self.boundaries.left),
+        ];
+        crossed.iter().any(|(crossed, _)| *crossed)
+            && crossed
+                .iter()
+                .all(|(crossed, boundary)| !crossed || *boundary == Boundary::Void)
+    }
+
+    /// Places `cell` at `pos` and returns the cell that was there before.
+    pub fn insert(&mut self, pos: &Vec2, cell: Cell) -> eyre::Result<Option<Cell>> {
+        let Some(idx) = self.index(pos) else {
+            return Err(eyre::eyre!(
+                "({}, {}) is outside of the {}x{} grid",
+                pos.x,
+                pos.y,
+                self.width,
+                self.height
+            ));
+        };
+        Ok(self.cells[idx].replace(cell))
+    }
+
+    pub fn remove(&mut self, pos: &Vec2) -> Option<Cell> {
+        self.index(pos).and_then(|idx| self.cells[idx].take())
+    }
+
+    /// Exchanges the contents of two positions, both of which have to be inside of the grid.
+    pub fn swap(&mut self, a: &Vec2, b: &Vec2) -> eyre::Result<()> {
+        match (self.index(a), self.index(b)) {
+            (Some(a), Some(b)) => {
+                self.cells.swap(a, b);
+                Ok(())
+            }
+            _ => Err(eyre::eyre!(
+                "cannot swap ({}, {}) with ({}, {}) outside of the {}x{} grid",
+                a.x,
+                a.y,
+                b.x,
+                b.y,
+                self.width,
+                self.height
+            )),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(None);
+    }
+
+    /// Changes the extent of the grid, keeping every cell that still fits.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        let mut resized = Self::new(width, height);
+        for (pos, cell) in self.iter() {
+            if let Some(idx) = resized.index(&pos) {
+                resized.cells[idx] = Some(cell.clone());
+            }
+        }
+        resized.boundaries = self.boundaries;
+        *self = resized;
+    }
+
+    /// Stretches or squeezes the grid to a new extent. Every cell takes the contents of the nearest
+    /// cell of the old grid, so squeezing drops cells and stretching duplicates them.
+    pub fn rescale(&mut self, width: u16, height: u16) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        let mut rescaled = Self::new(width, height);
+        for (idx, cell) in rescaled.cells.iter_mut().enumerate() {
+            let (x, y) = (idx % width as usize, idx / width as usize);
+            let from = Vec2 {
+                x: (x * self.width as usize / width as usize) as i16,
+                y: (y * self.height as usize / height as usize) as i16,
+            };
+            if let Some(from) = self.index(&from) {
+                *cell = self.cells[from].clone();
+            }
+        }
+        rescaled.boundaries = self.boundaries;
+        *self = rescaled;
+    }
+
+    /// Iterates over all occupied positions in row-major order.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec2, &Cell)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, cell)| cell.as_ref().map(|cell| (self.position(idx), cell)))
+    }
+
+    /// Number of occupied cells.
+    pub fn count(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.is_some()).count()
+    }
+}
+
+/// Read-only view of the cells surrounding a single position of a [`Grid`].
+pub struct Neighbourhood<'a> {
+    grid: &'a Grid,
+    materials: &'a MaterialRegistry,
+    pos: Vec2,
+}
+
+impl<'a> Neighbourhood<'a> {
+    pub fn new(grid: &'a Grid, materials: &'a MaterialRegistry, pos: Vec2) -> Self {
+        Self {
+            grid,
+            materials,
+            pos,
+        }
+    }
+
+    /// The materials the cells of the grid are made of.
+    pub fn materials(&self) -> &'a MaterialRegistry {
+        self.materials
+    }
+
+    /// Returns the neighbour in `direction`, see [`Grid::find`].
+    pub fn get(&self, direction: Direction) -> Option<&'a Cell> {
+        self.at(Vec2::from(direction))
+    }
+
+    /// Returns the cell at `offset` from the centre, which may lie beyond the direct neighbours but
+    /// no further than [`MAX_REACH`].
+    pub fn at(&self, offset: Vec2) -> Option<&'a Cell> {
+        debug_assert!(
+            offset.x.abs() <= MAX_REACH && offset.y.abs() <= MAX_REACH,
+            "a cell may look at most {MAX_REACH} cells away, not {offset:?}"
+        );
+        self.grid.find(&(&self.pos + offset))
+    }
+
+    pub fn is_empty(&self, direction: Direction) -> bool {
+        self.get(direction).is_none()
+    }
+
+    /// Iterates over all eight neighbours that are not empty.
+    pub fn iter(&self) -> impl Iterator<Item = (Direction, &'a Cell)> + '_ {
+        Direction::ALL
+            .into_iter()
+            .filter_map(|direction| self.get(direction).map(|cell| (direction, cell)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(name: &str) -> Cell {
+        let materials = MaterialRegistry::default();
+        Cell::new(materials.find(name).unwrap(), &materials, &mut rand::rng())
+    }
+
+    #[test]
+    fn test_resize_keeps_overlapping_cells() {
+        let mut grid = Grid::new(4, 4);
+        grid.insert(&(1i16, 1).into(), cell("sand")).unwrap();
+        grid.insert(&(3i16, 3).into(), cell("water")).unwrap();
+
+        grid.resize(2, 2);
+
+        assert_eq!(
+            grid.get(&(1i16, 1).into()).map(|cell| cell.material),
+            MaterialRegistry::default().find("sand")
+        );
+        assert_eq!(grid.count(), 1);
+    }
+
+    #[test]
+    fn test_rescale_picks_nearest_cells() {
+        let mut grid = Grid::new(4, 4);
+        grid.insert(&(0i16, 0).into(), cell("sand")).unwrap();
+        grid.insert(&(1i16, 0).into(), cell("water")).unwrap();
+        grid.insert(&(3i16, 2).into(), cell("sand")).unwrap();
+
+        grid.rescale(2, 2);
+
+        let positions: Vec<(i16, i16)> = grid.iter().map(|(pos, _)| pos.into()).collect();
+        assert_eq!(positions, vec![(0, 0)]);
+
+        grid.rescale(4, 4);
+        assert_eq!(grid.count(), 4);
+    }
+
+    #[test]
+    fn test_region_keeps_void_only_on_shared_edges() {
+        let mut grid = Grid::new(40, 40);
+        grid.set_boundaries(Boundaries::all(Boundary::Void));
+
+        let region = grid.region((-8i16, 20).into(), 32, 32);
+
+        assert!(region.is_void(&(-1i16, 30).into()));
+        assert!(region.is_void(&(10i16, 40).into()));
+        assert!(!region.is_void(&(24i16, 30).into()));
+        assert!(!region.is_void(&(10i16, 19).into()));
+    }
+
+    #[test]
+    fn test_insert_outside_is_error() {
+        let mut grid = Grid::new(2, 2);
+        assert!(grid.insert(&(2i16, 0).into(), cell("sand")).is_err());
+        assert!(grid.insert(&(0i16, -1).into(), cell("sand")).is_err());
+    }
+
+    #[test]
+    fn test_iter_is_row_major() {
+        let mut grid = Grid::new(3, 2);
+        grid.insert(&(2i16, 0).into(), cell("sand")).unwrap();
+        grid.insert(&(0i16, 1).into(), cell("water")).unwrap();
+
+        let positions: Vec<(i16, i16)> = grid.iter().map(|(pos, _)| pos.into()).collect();
+        assert_eq!(positions, vec![(2, 0), (0, 1)]);
+    }
+}
@@ -0,0 +1,551 @@
+//! Pictures of the world at cell resolution, and pixel art turned into worlds.
+//!
+//! Images are always read and written as PPM, which needs no dependency. Builds with the `png`
+//! feature support PNG as well.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use color_eyre::eyre::{self, WrapErr};
+
+use crate::{
+    coord::Vec2,
+    grid::MAX_EXTENT,
+    material::{MaterialId, MaterialRegistry},
+    simulation_widget::MaterialCanvas,
+};
+
+/// An RGB colour.
+pub type Rgb = (u8, u8, u8);
+
+/// A picture made of RGB pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    /// Pixels in row-major order.
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    /// Constructs an image of `width` by `height` pixels filled with `background`.
+    pub fn new(width: u32, height: u32, background: Rgb) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The colour of the pixel at `x`, `y`, `None` outside of the image.
+    pub fn get(&self, x: u32, y: u32) -> Option<Rgb> {
+        (x < self.width && y < self.height).then(|| self.pixels[(y * self.width + x) as usize])
+    }
+
+    /// Colours the pixel at `x`, `y`, pixels outside of the image are ignored.
+    pub fn set(&mut self, x: u32, y: u32, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[(y * self.width + x) as usize] = color;
+        }
+    }
+
+    /// Enlarges the image by an integer `factor`, every pixel becomes a `factor` by `factor`
+    /// square.
+    pub fn scaled(&self, factor: u32) -> Self {
+        let factor = factor.max(1);
+        let mut scaled = Self::new(self.width * factor, self.height * factor, (0, 0, 0));
+        for y in 0..scaled.height {
+            for x in 0..scaled.width {
+                scaled.pixels[(y * scaled.width + x) as usize] =
+                    self.pixels[(y / factor * self.width + x / factor) as usize];
+            }
+        }
+        scaled
+    }
+
+    /// Resamples the image to `width` by `height` pixels, picking the nearest pixel for each.
+    pub fn resized(&self, width: u32, height: u32) -> Self {
+        let mut resized = Self::new(width, height, (0, 0, 0));
+        if self.width == 0 || self.height == 0 {
+            return resized;
+        }
+        for y in 0..height {
+            for x in 0..width {
+                let from_x = (x as u64 * self.width as u64 / width as u64) as u32;
+                let from_y = (y as u64 * self.height as u64 / height as u64) as u32;
+                resized.pixels[(y * width + x) as usize] =
+                    self.pixels[(from_y * self.width + from_x) as usize];
+            }
+        }
+        resized
+    }
+
+    /// Paints a particle for every pixel onto `canvas`, with the upper left corner of the image at
+    /// `offset`. `colors` decides which material each pixel becomes.
+    pub fn paint(
+        &self,
+        canvas: &mut impl MaterialCanvas,
+        colors: &ColorMap,
+        offset: &Vec2,
+    ) -> eyre::Result<()> {
+        // grouped in the order materials first appear, so a seed always plays out the same way
+        let mut groups: Vec<(MaterialId, Vec<Vec2>)> = vec![];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = self.pixels[(y * self.width + x) as usize];
+                let Some(material) = colors.material(color) else {
+                    continue;
+                };
+                // pixels that land beyond any possible position can't be part of the world
+                let (Some(x), Some(y)) = (
+                    i16::try_from(x).ok().and_then(|x| x.checked_add(offset.x)),
+                    i16::try_from(y).ok().and_then(|y| y.checked_add(offset.y)),
+                ) else {
+                    continue;
+                };
+                let pos = Vec2 { x, y };
+                match groups.iter_mut().find(|(known, _)| *known == material) {
+                    Some((_, points)) => points.push(pos),
+                    None => groups.push((material, vec![pos])),
+                }
+            }
+        }
+        for (material, points) in groups {
+            canvas.set_pixels(&points, material)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a PPM image, either binary (`P6`) or plain text (`P3`).
+    pub fn read_ppm(bytes: &[u8]) -> eyre::Result<Self> {
+        let mut rest = bytes;
+        let mut header = [0u32; 3];
+        let magic = next_token(&mut rest);
+        let binary = match magic {
+            b"P6" => true,
+            b"P3" => false,
+            _ => eyre::bail!("not a PPM image"),
+        };
+        for (field, name) in header.iter_mut().zip(["width", "height", "maximum value"]) {
+            let token = next_token(&mut rest);
+            *field = std::str::from_utf8(token)
+                .ok()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| eyre::eyre!("invalid {name} in PPM header"))?;
+        }
+        let [width, height, max] = header;
+        if !(1..=255).contains(&max) {
+            eyre::bail!("only PPM images with up to 8 bits per channel are supported");
+        }
+        check_extent(width, height)?;
+        let count = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|count| count.checked_mul(3))
+            .ok_or_else(|| eyre::eyre!("PPM image is too large"))?;
+        let values: Vec<u8> = if binary {
+            // a single whitespace character separates the header from the pixels
+            let pixels = rest.get(1..1 + count);
+            pixels
+                .ok_or_else(|| eyre::eyre!("PPM image ends early"))?
+                .to_vec()
+        } else {
+            (0..count)
+                .map(|_| {
+                    std::str::from_utf8(next_token(&mut rest))
+                        .ok()
+                        .and_then(|token| token.parse().ok())
+                        .ok_or_else(|| eyre::eyre!("PPM image ends early"))
+                })
+                .collect::<eyre::Result<_>>()?
+        };
+        let channel = |value: u8| (value as u32 * 255 / max).min(255) as u8;
+        let pixels = values
+            .chunks_exact(3)
+            .map(|rgb| (channel(rgb[0]), channel(rgb[1]), channel(rgb[2])))
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Reads a PNG image. Transparent pixels turn black, like the background of exported images.
+    #[cfg(feature = "png")]
+    pub fn read_png(bytes: &[u8]) -> eyre::Result<Self> {
+        let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        check_extent(reader.info().width, reader.info().height)?;
+        let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+        let info = reader.next_frame(&mut buffer)?;
+        let opaque = |alpha: u8, color: Rgb| if alpha < 128 { (0, 0, 0) } else { color };
+        let pixels = match info.color_type {
+            png::ColorType::Rgb => buffer[..info.buffer_size()]
+                .chunks_exact(3)
+                .map(|px| (px[0], px[1], px[2]))
+                .collect(),
+            png::ColorType::Rgba => buffer[..info.buffer_size()]
+                .chunks_exact(4)
+                .map(|px| opaque(px[3], (px[0], px[1], px[2])))
+                .collect(),
+            png::ColorType::Grayscale => buffer[..info.buffer_size()]
+                .iter()
+                .map(|gray| (*gray, *gray, *gray))
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer[..info.buffer_size()]
+                .chunks_exact(2)
+                .map(|px| opaque(px[1], (px[0], px[0], px[0])))
+                .collect(),
+            png::ColorType::Indexed => eyre::bail!("indexed PNG images are not supported"),
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    /// Opens the image at `path`, as PNG if it ends in `.png` and as PPM otherwise.
+    pub fn open(path: &Path) -> eyre::Result<Self> {
+        let bytes =
+            fs::read(path).wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        let image = match is_png(path) {
+            #[cfg(feature = "png")]
+            true => Self::read_png(&bytes),
+            #[cfg(not(feature = "png"))]
+            true => Err(eyre::eyre!(
+                "this build has no PNG support, build with `--features png` or convert it to .ppm"
+            )),
+            false => Self::read_ppm(&bytes),
+        };
+        image.wrap_err_with(|| format!("invalid image {}", path.display()))
+    }
+
+    /// Writes the image as binary PPM (`P6`).
+    pub fn write_ppm(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.bytes())?;
+        Ok(())
+    }
+
+    /// Writes the image as PNG.
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, writer: &mut impl Write) -> eyre::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.bytes())?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Saves the image to `path`, as PNG if it ends in `.png` and as PPM otherwise.
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let is_png = is_png(path);
+        if is_png && cfg!(not(feature = "png")) {
+            eyre::bail!(
+                "can't write {}, this build has no PNG support, build with `--features png` or \
+                 save as .ppm",
+                path.display()
+            );
+        }
+        let file =
+            File::create(path).wrap_err_with(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let written = match is_png {
+            #[cfg(feature = "png")]
+            true => self.write_png(&mut writer),
+            _ => self.write_ppm(&mut writer),
+        };
+        written
+            .and_then(|()| Ok(writer.flush()?))
+            .wrap_err_with(|| format!("failed to write {}", path.display()))
+    }
+
+    /// The pixels as consecutive red, green and blue bytes.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|(r, g, b)| [*r, *g, *b])
+            .collect()
+    }
+}
+
+/// Decides which material each colour of an imported image becomes, by picking the closest of a
+/// few known colours.
+#[derive(Clone, Debug)]
+pub struct ColorMap {
+    /// Known colours and their material, `None` for empty cells.
+    entries: Vec<(Rgb, Option<MaterialId>)>,
+}
+
+impl ColorMap {
+    /// Matches colours to the materials they are drawn in, and black to empty cells. Shaded
+    /// particles of an exported picture still map to their material.
+    pub fn from_materials(materials: &MaterialRegistry) -> Self {
+        let mut entries = vec![((0, 0, 0), None)];
+        entries.extend(
+            materials
+                .iter()
+                .map(|(id, material)| (material.color, Some(id))),
+        );
+        Self { entries }
+    }
+
+    /// Matches colours to the given ones only.
+    pub fn new(entries: Vec<(Rgb, Option<MaterialId>)>) -> Self {
+        Self { entries }
+    }
+
+    /// The material of the known colour closest to `color`.
+    pub fn material(&self, color: Rgb) -> Option<MaterialId> {
+        let distance = |(r, g, b): Rgb| {
+            let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            channel(r, color.0) + channel(g, color.1) + channel(b, color.2)
+        };
+        self.entries
+            .iter()
+            .min_by_key(|(known, _)| distance(*known))
+            .and_then(|(_, material)| *material)
+    }
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+/// Splits the next whitespace separated token off `bytes`, skipping `#` comments.
+fn next_token<'a>(bytes: &mut &'a [u8]) -> &'a [u8] {
+    loop {
+        let start = bytes
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(bytes.len());
+        *bytes = &bytes[start..];
+        if bytes.first() != Some(&b'#') {
+            break;
+        }
+        let end = bytes
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .unwrap_or(bytes.len());
+        *bytes = &bytes[end..];
+    }
+    let end = bytes
+        .iter()
+        .position(|byte| byte.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let (token, rest) = bytes.split_at(end);
+    *bytes = rest;
+    token
+}
+
+/// Fails for images larger than the largest world, before any memory is set aside for their
+/// pixels.
+fn check_extent(width: u32, height: u32) -> eyre::Result<()> {
+    if width > MAX_EXTENT as u32 || height > MAX_EXTENT as u32 {
+        eyre::bail!(
+            "a {width}x{height} image is larger than any world, which holds at most {MAX_EXTENT} \
+             cells on each side"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation_widget::Simulation;
+
+    fn checkers() -> Image {
+        let mut image = Image::new(2, 2, (0, 0, 0));
+        image.set(0, 0, (255, 0, 0));
+        image.set(1, 1, (0, 0, 255));
+        image
+    }
+
+    #[test]
+    fn test_scaled_repeats_pixels() {
+        let image = checkers().scaled(3);
+
+        assert_eq!((image.width(), image.height()), (6, 6));
+        assert_eq!(image.get(2, 2), Some((255, 0, 0)));
+        assert_eq!(image.get(3, 2), Some((0, 0, 0)));
+        assert_eq!(image.get(5, 5), Some((0, 0, 255)));
+        assert_eq!(image.get(6, 5), None);
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let mut bytes = vec![];
+        checkers().write_ppm(&mut bytes).unwrap();
+
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend([255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255]);
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn test_read_ppm() {
+        let mut bytes = vec![];
+        checkers().write_ppm(&mut bytes).unwrap();
+        assert_eq!(Image::read_ppm(&bytes).unwrap(), checkers());
+
+        // plain text with a comment and a smaller maximum value
+        let plain = b"P3\n# sketch\n2 2 15\n15 0 0  0 0 0\n0 0 0  0 0 15\n";
+        assert_eq!(Image::read_ppm(plain).unwrap(), checkers());
+
+        let error = Image::read_ppm(b"P6\n2 2\n255\n\x00").err().unwrap();
+        assert_eq!(error.to_string(), "PPM image ends early");
+
+        let error = Image::read_ppm(b"P6 4000000000 4000000000 255\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "a 4000000000x4000000000 image is larger than any world, which holds at most 32751 \
+             cells on each side"
+        );
+    }
+
+    #[test]
+    fn test_resized_picks_nearest_pixel() {
+        let image = checkers().scaled(4).resized(2, 2);
+        assert_eq!(image, checkers());
+
+        let stretched = checkers().resized(4, 1);
+        assert_eq!(stretched.get(1, 0), Some((255, 0, 0)));
+        assert_eq!(stretched.get(2, 0), Some((0, 0, 0)));
+    }
+
+    #[test]
+    fn test_nearest_material_color() {
+        let materials = MaterialRegistry::default();
+        let colors = ColorMap::from_materials(&materials);
+        let sand = materials.find("sand").unwrap();
+        let (r, g, b) = materials.get(sand).color;
+
+        assert_eq!(colors.material((r, g, b)), Some(sand));
+        assert_eq!(colors.material((r - 10, g + 10, b)), Some(sand));
+        assert_eq!(colors.material((5, 5, 5)), None);
+    }
+
+    #[test]
+    fn test_paint_places_image_at_offset() {
+        let materials = MaterialRegistry::default();
+        let stone = materials.find("stone").unwrap();
+        let water = materials.find("water").unwrap();
+        let colors = ColorMap::new(vec![
+            ((255, 0, 0), Some(stone)),
+            ((0, 0, 255), Some(water)),
+            ((0, 0, 0), None),
+        ]);
+        let mut simulation = Simulation::new(materials);
+        simulation.set_world_size(10, 10);
+
+        checkers()
+            .scaled(2)
+            .paint(&mut simulation, &colors, &(3i16, 5).into())
+            .unwrap();
+
+        let level = simulation.level().unwrap().to_text(simulation.materials());
+        let map: Vec<&str> = level.lines().skip(4).collect();
+        assert_eq!(
+            map[4..],
+            [
+                "..........",
+                "...ss.....",
+                "...ss.....",
+                ".....ww...",
+                ".....ww...",
+                "..........",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paint_skips_pixels_beyond_coordinates() {
+        let materials = MaterialRegistry::default();
+        let colors = ColorMap::from_materials(&materials);
+        let sand = materials.get(materials.find("sand").unwrap()).color;
+        let image = Image::new(40, 1, sand);
+        let mut simulation = Simulation::new(materials);
+        simulation.set_world_size(MAX_EXTENT, 1);
+
+        image
+            .paint(&mut simulation, &colors, &(i16::MAX - 10, 0).into())
+            .unwrap();
+
+        assert_eq!(simulation.particle_count(), 0);
+        image
+            .paint(
+                &mut simulation,
+                &colors,
+                &(MAX_EXTENT as i16 - 10, 0).into(),
+            )
+            .unwrap();
+        assert_eq!(simulation.particle_count(), 10);
+    }
+
+    #[test]
+    fn test_paint_is_reproducible() {
+        let materials = MaterialRegistry::default();
+        let colors = ColorMap::from_materials(&materials);
+        let mut image = Image::new(8, 8, (0, 0, 0));
+        for (idx, (_, material)) in materials.iter().enumerate() {
+            image.set(idx as u32 % 8, idx as u32 / 8, material.color);
+        }
+        let import = || {
+            let mut simulation = Simulation::with_seed(materials.clone(), 7);
+            simulation.set_world_size(8, 8);
+            image
+                .paint(&mut simulation, &colors, &Vec2::default())
+                .unwrap();
+            for _ in 0..30 {
+                simulation.handle_ticks().unwrap();
+            }
+            let mut bytes = vec![];
+            simulation.save(&mut bytes).unwrap();
+            bytes
+        };
+
+        assert_eq!(import(), import());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_read_png() {
+        let mut bytes = vec![];
+        checkers().write_png(&mut bytes).unwrap();
+
+        assert_eq!(Image::read_png(&bytes).unwrap(), checkers());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_write_png() {
+        let mut bytes = vec![];
+        checkers().write_png(&mut bytes).unwrap();
+
+        let decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(&pixels[..3], &[255, 0, 0]);
+        assert_eq!(&pixels[9..], &[0, 0, 255]);
+    }
+}
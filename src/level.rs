@@ -0,0 +1,326 @@
+//! Plain text levels, meant to be written by hand and diffed in version control.
+//!
+//! A level starts with a legend that maps one character to a material per line, followed by an
+//! empty line and the map itself. Every character of the map is one cell, a space is always empty:
+//!
+//! ```text
+//! # = wood
+//! . = sand
+//! ~ = water
+//!
+//!   ....
+//!  ~~....~~
+//! ##########
+//! ```
+//!
+//! All lines of the map must be equally long, trailing spaces included. Editors that strip trailing
+//! spaces leave the lines ragged, so the legend may also map a visible character to `empty`, as
+//! exported levels do with `.`. Empty lines after the map are ignored.
+
+use std::fmt::Write;
+
+use color_eyre::eyre;
+
+use crate::{
+    coord::Vec2,
+    grid::Grid,
+    material::{EMPTY, MaterialId, MaterialRegistry},
+    simulation_widget::MaterialCanvas,
+};
+
+/// Characters handed out to materials whose name doesn't start with a free one.
+const SPARE_GLYPHS: &str = "#@%&*+~=-:;!?$^0123456789";
+/// Character exported levels use for empty cells.
+const EMPTY_GLYPH: char = '.';
+
+/// A scene read from or written to the text format described in [`crate::level`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    /// The character standing for each material, in the order of the legend, `None` for empty.
+    legend: Vec<(char, Option<MaterialId>)>,
+    /// Rows of the map from top to bottom, `None` for empty cells.
+    rows: Vec<Vec<Option<MaterialId>>>,
+}
+
+impl Level {
+    /// Parses a level, errors point to the offending line of `source`.
+    pub fn parse(source: &str, materials: &MaterialRegistry) -> eyre::Result<Self> {
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line));
+
+        let mut legend: Vec<(char, Option<MaterialId>)> = vec![];
+        for (number, line) in lines.by_ref() {
+            if line.trim().is_empty() {
+                break;
+            }
+            let mut chars = line.chars();
+            let glyph = chars.next().filter(|glyph| *glyph != ' ');
+            let name = chars.as_str().trim_start().strip_prefix('=').map(str::trim);
+            let (Some(glyph), Some(name)) = (glyph, name) else {
+                eyre::bail!("line {number}: expected `<character> = <material>`, got `{line}`");
+            };
+            if legend.iter().any(|(known, _)| *known == glyph) {
+                eyre::bail!("line {number}: `{glyph}` is already in the legend");
+            }
+            let material = if name.eq_ignore_ascii_case(EMPTY) {
+                None
+            } else {
+                let material = materials
+                    .iter()
+                    .find(|(_, material)| material.name.eq_ignore_ascii_case(name))
+                    .map(|(id, _)| id)
+                    .ok_or_else(|| eyre::eyre!("line {number}: unknown material `{name}`"))?;
+                Some(material)
+            };
+            legend.push((glyph, material));
+        }
+
+        let mut map: Vec<(usize, &str)> = lines.collect();
+        // editors like to end a file with an empty line, it isn't part of the map
+        while map.last().is_some_and(|(_, line)| line.is_empty()) {
+            map.pop();
+        }
+        let mut rows = vec![];
+        let mut width = None;
+        for (number, line) in map {
+            let length = line.chars().count();
+            let expected = *width.get_or_insert(length);
+            if length != expected {
+                eyre::bail!(
+                    "line {number}: map lines must all be {expected} characters long, this one is \
+                     {length}"
+                );
+            }
+            let row = line
+                .chars()
+                .map(|glyph| match glyph {
+                    ' ' => Ok(None),
+                    _ => legend
+                        .iter()
+                        .find(|(known, _)| *known == glyph)
+                        .map(|(_, material)| *material)
+                        .ok_or_else(|| {
+                            eyre::eyre!("line {number}: `{glyph}` is not in the legend")
+                        }),
+                })
+                .collect::<eyre::Result<_>>()?;
+            rows.push(row);
+        }
+        Ok(Self { legend, rows })
+    }
+
+    /// Captures the particles of `grid`, picking a character for each material that is present.
+    /// Fails if there are more materials than characters to tell them apart.
+    pub fn from_grid(grid: &Grid, materials: &MaterialRegistry) -> eyre::Result<Self> {
+        let mut rows = vec![vec![None; grid.width() as usize]; grid.height() as usize];
+        for (pos, cell) in grid.iter() {
+            rows[pos.y as usize][pos.x as usize] = Some(cell.material);
+        }
+
+        let mut legend: Vec<(char, Option<MaterialId>)> = vec![];
+        if rows.iter().flatten().any(Option::is_none) {
+            legend.push((EMPTY_GLYPH, None));
+        }
+        for (material, definition) in materials.iter() {
+            if !rows.iter().flatten().any(|cell| *cell == Some(material)) {
+                continue;
+            }
+            let first = definition.name.chars().next();
+            let glyph = first
+                .into_iter()
+                .chain(first.map(|first| first.to_ascii_uppercase()))
+                .chain(SPARE_GLYPHS.chars())
+                .chain('!'..='~')
+                .find(|glyph| {
+                    glyph.is_ascii_graphic() && legend.iter().all(|(known, _)| known != glyph)
+                })
+                .ok_or_else(|| {
+                    eyre::eyre!(
+                        "there are more materials than characters to write them down, `{}` has \
+                         none left",
+                        definition.name
+                    )
+                })?;
+            legend.push((glyph, Some(material)));
+        }
+        Ok(Self { legend, rows })
+    }
+
+    /// Number of cells per row of the map.
+    pub fn width(&self) -> u16 {
+        self.rows.first().map_or(0, |row| row.len() as u16)
+    }
+
+    /// Number of rows of the map.
+    pub fn height(&self) -> u16 {
+        self.rows.len() as u16
+    }
+
+    /// Paints the level onto `canvas` with its upper left corner at `offset`. Empty cells leave
+    /// the canvas as it is.
+    pub fn paint(&self, canvas: &mut impl MaterialCanvas, offset: &Vec2) -> eyre::Result<()> {
+        for (_, material) in &self.legend {
+            let Some(material) = material else {
+                continue;
+            };
+            let points: Vec<Vec2> = self
+                .cells()
+                .filter(|(_, cell)| *cell == *material)
+                .map(|(pos, _)| &pos + offset)
+                .collect();
+            canvas.set_pixels(&points, *material)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the level in the text format, naming the materials as `materials` does.
+    pub fn to_text(&self, materials: &MaterialRegistry) -> String {
+        let mut text = String::new();
+        for (glyph, material) in &self.legend {
+            let name = material.map_or(EMPTY, |material| &materials.get(material).name);
+            let _ = writeln!(text, "{glyph} = {name}");
+        }
+        text.push('\n');
+        for row in &self.rows {
+            for cell in row {
+                let glyph = self
+                    .legend
+                    .iter()
+                    .find(|(_, known)| known == cell)
+                    .map_or(' ', |(glyph, _)| *glyph);
+                text.push(glyph);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Positions and materials of all cells that aren't empty.
+    fn cells(&self) -> impl Iterator<Item = (Vec2, MaterialId)> + '_ {
+        self.rows.iter().enumerate().flat_map(|(y, row)| {
+            row.iter().enumerate().filter_map(move |(x, cell)| {
+                cell.map(|material| ((x as i16, y as i16).into(), material))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation_widget::Simulation;
+
+    const LEVEL: &str = "\
+# = Wood
+. = sand
+~ = water
+
+  ..  
+~~..~~
+######
+";
+
+    #[test]
+    fn test_parse_maps_glyphs_to_materials() {
+        let materials = MaterialRegistry::default();
+        let level = Level::parse(LEVEL, &materials).unwrap();
+
+        assert_eq!((level.width(), level.height()), (6, 3));
+        let count = |name: &str| {
+            let material = materials.find(name).unwrap();
+            level.cells().filter(|(_, cell)| *cell == material).count()
+        };
+        assert_eq!((count("sand"), count("water"), count("wood")), (4, 4, 6));
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let materials = MaterialRegistry::default();
+        let level = Level::parse(LEVEL, &materials).unwrap();
+        let mut simulation = Simulation::new(materials.clone());
+        simulation.set_world_size(level.width(), level.height());
+        level.paint(&mut simulation, &Vec2::default()).unwrap();
+
+        let text = simulation.level().unwrap().to_text(&materials);
+
+        assert_eq!(
+            text,
+            ". = empty\ns = sand\nw = water\nW = wood\n\n..ss..\nwwssww\nWWWWWW\n"
+        );
+        let reparsed = Level::parse(&text, &materials).unwrap();
+        assert_eq!(
+            reparsed.cells().collect::<Vec<_>>(),
+            level.cells().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_trailing_empty_lines_are_ignored() {
+        let materials = MaterialRegistry::default();
+        let level = Level::parse("# = wood\n\n##\n##\n\n\n", &materials).unwrap();
+
+        assert_eq!((level.width(), level.height()), (2, 2));
+    }
+
+    #[test]
+    fn test_empty_glyph_keeps_lines_even() {
+        let materials = MaterialRegistry::default();
+        let level = Level::parse(". = empty\n# = wood\n\n.#.\n###\n", &materials).unwrap();
+
+        assert_eq!((level.width(), level.height()), (3, 2));
+        assert_eq!(level.cells().count(), 4);
+    }
+
+    #[test]
+    fn test_too_many_materials_is_error() {
+        let source: String = (0..100)
+            .map(|idx| {
+                format!(
+                    "[[material]]\nname = \"m{idx:02}\"\ncolor = \"#808080\"\ndensity = 1.0\n\
+                     state = \"solid\"\nbehaviour = \"static\"\n"
+                )
+            })
+            .collect();
+        let materials = MaterialRegistry::parse(&source).unwrap();
+        let mut simulation = Simulation::new(materials.clone());
+        simulation.set_world_size(100, 1);
+        for (idx, (material, _)) in materials.iter().enumerate() {
+            simulation
+                .set_pixels(&[(idx as i16, 0).into()], material)
+                .unwrap();
+        }
+
+        let error = simulation.level().unwrap_err().to_string();
+
+        assert_eq!(
+            error,
+            "there are more materials than characters to write them down, `m94` has none left"
+        );
+    }
+
+    #[test]
+    fn test_errors_point_to_line() {
+        let materials = MaterialRegistry::default();
+        let error = |source: &str| Level::parse(source, &materials).err().unwrap().to_string();
+
+        assert_eq!(
+            error("# = wood\n\n##\n#?\n"),
+            "line 4: `?` is not in the legend"
+        );
+        assert_eq!(
+            error("# = wood\n\n##\n\n##\n"),
+            "line 4: map lines must all be 2 characters long, this one is 0"
+        );
+        assert_eq!(
+            error("# = wood\n\n###\n#\n"),
+            "line 4: map lines must all be 3 characters long, this one is 1"
+        );
+        assert_eq!(error("# = cheese\n"), "line 1: unknown material `cheese`");
+        assert_eq!(
+            error("wood\n"),
+            "line 1: expected `<character> = <material>`, got `wood`"
+        );
+    }
+}
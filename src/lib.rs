@@ -0,0 +1,20 @@
+pub mod app;
+pub mod behaviour;
+pub mod brush;
+pub mod chunk;
+pub mod cli;
+pub mod coord;
+pub mod event;
+pub mod grid;
+pub mod image;
+pub mod level;
+pub mod material;
+pub mod particle;
+pub mod reaction;
+pub mod recorder;
+pub mod save;
+pub mod simulation_widget;
+pub mod stats;
+pub mod tick;
+pub mod ui;
+pub mod window;
@@ -1,20 +1,161 @@
+use std::{fs, path::Path};
+
+use clap::Parser;
+use color_eyre::eyre::{self, WrapErr};
 use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 
-use crate::app::App;
+use sandbox::{
+    app::App,
+    cli::{Cli, Command},
+    coord::Vec2,
+    grid::MAX_EXTENT,
+    image::{ColorMap, Image},
+    level::Level,
+    material::{EMPTY, MaterialRegistry},
+    recorder::Recorder,
+    simulation_widget::Simulation,
+};
 
-pub mod app;
-pub(crate) mod coord;
-pub mod event;
-pub mod particle;
-mod simulation_widget;
-pub mod ui;
-pub mod window;
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
+    let cli = Cli::parse();
+    // load everything before the terminal is taken over, so errors are readable
+    let materials = match &cli.materials {
+        Some(path) => MaterialRegistry::load(path)?,
+        None => MaterialRegistry::default(),
+    };
+    let mut simulation = match cli.seed {
+        Some(seed) => Simulation::with_seed(materials, seed),
+        None => Simulation::new(materials),
+    };
+    simulation.set_resize_policy(cli.resize);
+    if let Some((width, height)) = cli.world {
+        simulation.set_world_size(width, height);
+    }
+    if let Some(path) = &cli.load {
+        simulation.load_file(path)?;
+    }
+    // boundaries on the command line win over those of a loaded world
+    if let Some(boundaries) = cli.boundary {
+        simulation.set_boundaries(boundaries);
+    }
+    if let Some(path) = &cli.level {
+        let source = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read level {}", path.display()))?;
+        let level = Level::parse(&source, simulation.materials())
+            .wrap_err_with(|| format!("invalid level {}", path.display()))?;
+        if cli.world.is_none() && cli.load.is_none() {
+            simulation.set_world_size(level.width(), level.height());
+        }
+        level.paint(&mut simulation, &Vec2::default())?;
+    }
+    if let Some(path) = &cli.import {
+        import(&mut simulation, path, &cli)?;
+    }
+    match &cli.command {
+        Some(Command::Export { output, ticks }) => {
+            return export(simulation, output, *ticks, cli.scale);
+        }
+        Some(Command::Record { output, ticks }) => {
+            let recorder = Recorder::new(cli.record_every, cli.max_frames);
+            return record(simulation, recorder, output, *ticks, cli.scale);
+        }
+        None => {}
+    }
+    let mut app = App::with_simulation(simulation);
+    app.image_scale = cli.scale;
+    app.record_every = cli.record_every;
+    app.max_frames = cli.max_frames;
+    if let Some(path) = cli.load {
+        app.save_path = path;
+    }
+
     let terminal = ratatui::init();
     crossterm::execute!(std::io::stdout(), EnableMouseCapture)?;
-    let result = App::new().run(terminal);
+    let result = app.run(terminal);
     ratatui::restore();
     crossterm::execute!(std::io::stdout(), DisableMouseCapture)?;
     result
 }
+
+/// Runs the simulation for `ticks` ticks without a terminal and saves a picture of the outcome.
+fn export(mut simulation: Simulation, output: &Path, ticks: u64, scale: u32) -> eyre::Result<()> {
+    require_world(&simulation)?;
+    for _ in 0..ticks {
+        simulation.handle_ticks()?;
+    }
+    simulation.snapshot().scaled(scale).save(output)
+}
+
+/// Paints the image at `path` into the world as the `--import` options describe.
+fn import(simulation: &mut Simulation, path: &Path, cli: &Cli) -> eyre::Result<()> {
+    let image = Image::open(path)?;
+    let size = |extent: u32| {
+        let size = ((extent as f32 * cli.import_scale).round() as u32).max(1);
+        if size > MAX_EXTENT as u32 {
+            eyre::bail!(
+                "--import-scale {} makes the image {size} cells across, a world holds at most \
+                 {MAX_EXTENT}",
+                cli.import_scale
+            );
+        }
+        Ok(size)
+    };
+    let image = image.resized(size(image.width())?, size(image.height())?);
+    let materials = simulation.materials();
+    let colors = if cli.import_color.is_empty() {
+        ColorMap::from_materials(materials)
+    } else {
+        let entries = cli
+            .import_color
+            .iter()
+            .map(|(color, name)| match name.as_str() {
+                EMPTY => Ok((*color, None)),
+                // the border is reserved for what lies beyond the edges, iter() leaves it out
+                _ => materials
+                    .iter()
+                    .find(|(_, material)| material.name == *name)
+                    .map(|(material, _)| (*color, Some(material)))
+                    .ok_or_else(|| eyre::eyre!("unknown material `{name}` in --import-color")),
+            })
+            .collect::<eyre::Result<_>>()?;
+        ColorMap::new(entries)
+    };
+    if cli.world.is_none() && cli.load.is_none() && cli.level.is_none() {
+        let (x, y) = cli.import_offset;
+        let extent = |pixels: u32, offset: i16| {
+            (pixels + offset.max(0) as u32).min(MAX_EXTENT as u32) as u16
+        };
+        simulation.set_world_size(extent(image.width(), x), extent(image.height(), y));
+    }
+    image.paint(simulation, &colors, &cli.import_offset.into())
+}
+
+/// Runs the simulation for `ticks` ticks without a terminal and saves the frames `recorder` took.
+fn record(
+    mut simulation: Simulation,
+    mut recorder: Recorder,
+    output: &Path,
+    ticks: u64,
+    scale: u32,
+) -> eyre::Result<()> {
+    require_world(&simulation)?;
+    recorder.capture(&simulation);
+    for _ in 0..ticks {
+        if recorder.is_full() {
+            break;
+        }
+        simulation.handle_ticks()?;
+        recorder.step(&simulation);
+    }
+    recorder.save(output, scale)
+}
+
+fn require_world(simulation: &Simulation) -> eyre::Result<()> {
+    if simulation.width() == 0 || simulation.height() == 0 {
+        eyre::bail!(
+            "there is no world to simulate, set one up with --world, --load, --level or --import"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,821 @@
+use std::{fs, ops::Range, path::Path, sync::Arc};
+
+use color_eyre::eyre::{self, WrapErr};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    behaviour::{BehaviourKind, Fire, Gas, Life, Liquid, MaterialBehaviour, Powder, Static},
+    chunk::MAX_REACH,
+    particle::AMBIENT_TEMPERATURE,
+    reaction::{Product, Reaction, ReactionTable},
+};
+
+/// The material definitions shipped with the sandbox.
+pub const DEFAULT_MATERIALS: &str = include_str!("../assets/materials.toml");
+
+/// Index of a material inside of its [`MaterialRegistry`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct MaterialId(u8);
+
+impl MaterialId {
+    /// Every registry starts with the border, which stands in for everything outside of the grid.
+    pub const BORDER: MaterialId = MaterialId(0);
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    pub(crate) fn from_index(idx: usize) -> Self {
+        Self(idx as u8)
+    }
+}
+
+/// Name that stands for "no particle at all" in reactions, it cannot be used for a material.
+pub const EMPTY: &str = "empty";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateOfMatter {
+    Solid,
+    Powder,
+    Liquid,
+    Gas,
+}
+
+impl StateOfMatter {
+    /// Whether other particles may pass through this material by swapping places with it.
+    pub fn is_fluid(&self) -> bool {
+        matches!(self, StateOfMatter::Liquid | StateOfMatter::Gas)
+    }
+}
+
+/// Turns a particle into `becomes` once its temperature crosses `temperature`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PhaseChange {
+    pub temperature: f32,
+    pub becomes: MaterialId,
+}
+
+/// Spawns a particle of `material` in the empty cell above, with a chance of `chance` per tick.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Emission {
+    pub material: MaterialId,
+    pub chance: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct MaterialDef {
+    pub name: String,
+    pub color: (u8, u8, u8),
+    /// Density in kg/m³, heavier materials sink through lighter fluids.
+    pub density: f32,
+    pub state: StateOfMatter,
+    pub behaviour: Arc<dyn MaterialBehaviour>,
+    /// Chance per tick and burning neighbour to catch fire.
+    pub flammability: f32,
+    /// Range the lifetime of new particles is picked from, `None` if they live forever.
+    pub lifetime: Option<(u16, u16)>,
+    /// Temperature of new particles in degrees celsius.
+    pub temperature: f32,
+    /// How quickly heat flows between this material and its surroundings, from 0 to 1.
+    pub conductivity: f32,
+    /// Melting, boiling or ignition point: what the material turns into above a temperature.
+    pub heated: Option<PhaseChange>,
+    /// Freezing or condensation point: what the material turns into below a temperature.
+    pub cooled: Option<PhaseChange>,
+    /// Particles given off by the material, like the smoke of a fire.
+    pub emits: Option<Emission>,
+    /// Reactions with neighbouring particles, as declared in the material file.
+    pub reactions: Vec<Reaction>,
+}
+
+/// All materials known to a simulation.
+///
+/// The registry is loaded from a TOML file, see `assets/materials.toml` for the format and the
+/// built-in materials.
+#[derive(Clone, Debug)]
+pub struct MaterialRegistry {
+    materials: Vec<MaterialDef>,
+    reactions: ReactionTable,
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        Self::parse(DEFAULT_MATERIALS).expect("the built-in materials are valid")
+    }
+}
+
+impl MaterialRegistry {
+    /// Loads the material definitions from the file at `path`.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let source = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read material file {}", path.display()))?;
+        Self::parse(&source).wrap_err_with(|| format!("invalid material file {}", path.display()))
+    }
+
+    /// Parses material definitions, errors point to the offending line of `source`.
+    pub fn parse(source: &str) -> eyre::Result<Self> {
+        let file: MaterialFile = toml::from_str(source).map_err(|error| {
+            let line = error
+                .span()
+                .map(|span| line_of(source, &span))
+                .unwrap_or_default();
+            eyre::eyre!("line {line}: {}", error.message())
+        })?;
+
+        let mut materials = vec![MaterialDef {
+            name: "border".to_string(),
+            color: (0, 255, 255),
+            density: f32::INFINITY,
+            state: StateOfMatter::Solid,
+            behaviour: Arc::new(Static),
+            flammability: 0.0,
+            lifetime: None,
+            temperature: AMBIENT_TEMPERATURE,
+            conductivity: 0.0,
+            heated: None,
+            cooled: None,
+            emits: None,
+            reactions: vec![],
+        }];
+        if file.materials.len() >= u8::MAX as usize {
+            return Err(eyre::eyre!(
+                "at most {} materials are supported, found {}",
+                u8::MAX - 1,
+                file.materials.len()
+            ));
+        }
+        for entry in &file.materials {
+            materials.push(entry.validate(source, &materials)?);
+        }
+
+        let mut registry = Self {
+            materials,
+            reactions: ReactionTable::default(),
+        };
+        // references to other materials can only be resolved once all of them are known
+        for (id, entry) in file.materials.iter().enumerate() {
+            let reactions = entry
+                .reactions
+                .iter()
+                .map(|reaction| reaction.resolve(source, &registry))
+                .collect::<eyre::Result<Vec<_>>>()?;
+            let heated = entry
+                .heated
+                .as_ref()
+                .map(|heated| phase_change(heated.above, &heated.becomes, source, &registry))
+                .transpose()?;
+            let cooled = entry
+                .cooled
+                .as_ref()
+                .map(|cooled| phase_change(cooled.below, &cooled.becomes, source, &registry))
+                .transpose()?;
+            let emits = entry
+                .emits
+                .as_ref()
+                .map(|emits| emits.resolve(source, &registry))
+                .transpose()?;
+            if let (Some(heated), Some(cooled)) = (heated, cooled)
+                && heated.temperature <= cooled.temperature
+            {
+                return invalid(
+                    source,
+                    entry.name.span(),
+                    format!(
+                        "`{}` has to be heated above a higher temperature than it is cooled below",
+                        entry.name.get_ref()
+                    ),
+                );
+            }
+
+            let material = &mut registry.materials[id + 1];
+            material.reactions = reactions;
+            material.heated = heated;
+            material.cooled = cooled;
+            material.emits = emits;
+        }
+        registry.reactions = ReactionTable::new(&registry.materials);
+        Ok(registry)
+    }
+
+    /// Returns the definition of `id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was not handed out by this registry.
+    pub fn get(&self, id: MaterialId) -> &MaterialDef {
+        &self.materials[id.index()]
+    }
+
+    /// All reactions of `material` with a neighbouring particle of `with`.
+    pub fn reactions(&self, material: MaterialId, with: MaterialId) -> &[Reaction] {
+        self.reactions.get(material, with)
+    }
+
+    /// Replaces the behaviour of `id`, for materials that need more than the presets of the material
+    /// file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was not handed out by this registry.
+    pub fn set_behaviour(&mut self, id: MaterialId, behaviour: Arc<dyn MaterialBehaviour>) {
+        self.materials[id.index()].behaviour = behaviour;
+        self.reactions = ReactionTable::new(&self.materials);
+    }
+
+    /// Looks up a material by its name.
+    pub fn find(&self, name: &str) -> Option<MaterialId> {
+        self.materials
+            .iter()
+            .position(|material| material.name == name)
+            .map(|idx| MaterialId(idx as u8))
+    }
+
+    /// Iterates over all materials that can be placed, which is everything but the border.
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialId, &MaterialDef)> + '_ {
+        self.materials
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(idx, material)| (MaterialId(idx as u8), material))
+    }
+}
+
+/// 1-based line number of the start of `span`.
+fn line_of(source: &str, span: &Range<usize>) -> usize {
+    source[..span.start.min(source.len())].matches('\n').count() + 1
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialFile {
+    #[serde(rename = "material", default)]
+    materials: Vec<MaterialEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialEntry {
+    name: Spanned<String>,
+    color: Spanned<String>,
+    density: Spanned<f32>,
+    state: StateOfMatter,
+    behaviour: Spanned<BehaviourEntry>,
+    #[serde(default)]
+    flammability: Option<Spanned<f32>>,
+    #[serde(default)]
+    lifetime: Option<Spanned<(u16, u16)>>,
+    #[serde(default)]
+    temperature: Option<f32>,
+    #[serde(default)]
+    conductivity: Option<Spanned<f32>>,
+    #[serde(default)]
+    heated: Option<HeatedEntry>,
+    #[serde(default)]
+    cooled: Option<CooledEntry>,
+    #[serde(default)]
+    emits: Option<EmitsEntry>,
+    #[serde(default)]
+    reactions: Vec<ReactionEntry>,
+}
+
+/// Either just the name of a behaviour preset, or a table with the name as `kind` and its
+/// parameters.
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "a behaviour like \"powder\" or a table like `{ kind = \"powder\", slip = 0.5 }`"
+)]
+enum BehaviourEntry {
+    Kind(BehaviourKind),
+    Table(BehaviourTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BehaviourTable {
+    kind: BehaviourKind,
+    #[serde(default)]
+    slip: Option<f32>,
+    #[serde(default)]
+    dispersion: Option<u16>,
+    #[serde(default)]
+    viscosity: Option<f32>,
+    #[serde(default)]
+    growth: Option<f32>,
+    #[serde(default)]
+    survive: Option<(u8, u8)>,
+}
+
+impl BehaviourTable {
+    fn build(&self, source: &str, span: Range<usize>) -> eyre::Result<Arc<dyn MaterialBehaviour>> {
+        let fraction = |value: Option<f32>, what: &str, default: f32| {
+            let value = value.map(|value| Spanned::new(span.clone(), value));
+            fraction(source, &value, what, default)
+        };
+        let (powder, liquid, life) = (
+            self.slip.is_some(),
+            self.dispersion.is_some() || self.viscosity.is_some(),
+            self.growth.is_some() || self.survive.is_some(),
+        );
+        let unused = match self.kind {
+            BehaviourKind::Powder => liquid || life,
+            BehaviourKind::Liquid => powder || life,
+            BehaviourKind::Life => powder || liquid,
+            _ => powder || liquid || life,
+        };
+        if unused {
+            return invalid(
+                source,
+                span,
+                format!(
+                    "the {:?} behaviour does not take these parameters",
+                    self.kind
+                ),
+            );
+        }
+
+        Ok(match self.kind {
+            BehaviourKind::Powder => Arc::new(Powder {
+                slip: fraction(self.slip, "slip", Powder::default().slip)?,
+            }),
+            BehaviourKind::Life => {
+                let default = Life::default();
+                let survive = self.survive.unwrap_or(default.survive);
+                if survive.0 > survive.1 || survive.1 > 8 {
+                    return invalid(
+                        source,
+                        span,
+                        "survive has to be a range [min, max] with min <= max <= 8".to_string(),
+                    );
+                }
+                Arc::new(Life {
+                    growth: fraction(self.growth, "growth", default.growth)?,
+                    survive,
+                })
+            }
+            BehaviourKind::Liquid => {
+                let default = Liquid::default();
+                let dispersion = self.dispersion.unwrap_or(default.dispersion);
+                if dispersion == 0 || dispersion > MAX_REACH as u16 {
+                    return invalid(
+                        source,
+                        span,
+                        format!("dispersion has to be between 1 and {MAX_REACH}"),
+                    );
+                }
+                Arc::new(Liquid {
+                    dispersion,
+                    viscosity: fraction(self.viscosity, "viscosity", default.viscosity)?,
+                })
+            }
+            BehaviourKind::Static => Arc::new(Static),
+            BehaviourKind::Fire => Arc::new(Fire),
+            BehaviourKind::Gas => Arc::new(Gas),
+        })
+    }
+}
+
+impl BehaviourEntry {
+    fn build(&self, source: &str, span: Range<usize>) -> eyre::Result<Arc<dyn MaterialBehaviour>> {
+        let table = match self {
+            BehaviourEntry::Kind(kind) => &BehaviourTable {
+                kind: *kind,
+                slip: None,
+                dispersion: None,
+                viscosity: None,
+                growth: None,
+                survive: None,
+            },
+            BehaviourEntry::Table(table) => table,
+        };
+        table.build(source, span)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeatedEntry {
+    above: f32,
+    becomes: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CooledEntry {
+    below: f32,
+    becomes: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmitsEntry {
+    material: Spanned<String>,
+    #[serde(default)]
+    chance: Option<Spanned<f32>>,
+}
+
+impl EmitsEntry {
+    fn resolve(&self, source: &str, registry: &MaterialRegistry) -> eyre::Result<Emission> {
+        Ok(Emission {
+            material: find_material(&self.material, source, registry)?,
+            chance: fraction(source, &self.chance, "chance of an emission", 1.0)?,
+        })
+    }
+}
+
+fn phase_change(
+    temperature: f32,
+    becomes: &Spanned<String>,
+    source: &str,
+    registry: &MaterialRegistry,
+) -> eyre::Result<PhaseChange> {
+    Ok(PhaseChange {
+        temperature,
+        becomes: find_material(becomes, source, registry)?,
+    })
+}
+
+fn find_material(
+    name: &Spanned<String>,
+    source: &str,
+    registry: &MaterialRegistry,
+) -> eyre::Result<MaterialId> {
+    match registry.find(name.get_ref()) {
+        // the border only stands in for the edges, it never exists as a particle
+        Some(MaterialId::BORDER) => invalid(
+            source,
+            name.span(),
+            format!(
+                "`{}` is reserved for the edges of the world",
+                name.get_ref()
+            ),
+        ),
+        Some(material) => Ok(material),
+        None => invalid(
+            source,
+            name.span(),
+            format!("unknown material `{}`", name.get_ref()),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionEntry {
+    with: Spanned<String>,
+    #[serde(default)]
+    chance: Option<Spanned<f32>>,
+    #[serde(default)]
+    becomes: Option<Spanned<String>>,
+    #[serde(default)]
+    other_becomes: Option<Spanned<String>>,
+}
+
+/// Validates an optional value between 0 and 1.
+fn fraction(
+    source: &str,
+    value: &Option<Spanned<f32>>,
+    what: &str,
+    default: f32,
+) -> eyre::Result<f32> {
+    match value {
+        Some(value) if !(0.0..=1.0).contains(value.get_ref()) => invalid(
+            source,
+            value.span(),
+            format!("{what} has to be between 0 and 1, got {}", value.get_ref()),
+        ),
+        Some(value) => Ok(*value.get_ref()),
+        None => Ok(default),
+    }
+}
+
+fn invalid<T>(source: &str, span: Range<usize>, message: String) -> eyre::Result<T> {
+    Err(eyre::eyre!("line {}: {message}", line_of(source, &span)))
+}
+
+impl MaterialEntry {
+    fn validate(&self, source: &str, known: &[MaterialDef]) -> eyre::Result<MaterialDef> {
+        let name = self.name.get_ref();
+        if name.is_empty() || name == EMPTY || known.iter().any(|material| &material.name == name) {
+            return invalid(
+                source,
+                self.name.span(),
+                format!("material name `{name}` is empty, reserved or already taken"),
+            );
+        }
+
+        let color = parse_color(self.color.get_ref()).ok_or_else(|| {
+            eyre::eyre!(
+                "line {}: `{}` is not a colour of the form #rrggbb",
+                line_of(source, &self.color.span()),
+                self.color.get_ref()
+            )
+        })?;
+
+        let density = *self.density.get_ref();
+        if !(density > 0.0 && density.is_finite()) {
+            return invalid(
+                source,
+                self.density.span(),
+                format!("density of `{name}` has to be positive, got {density}"),
+            );
+        }
+
+        let flammability = fraction(source, &self.flammability, "flammability", 0.0)?;
+        let conductivity = fraction(source, &self.conductivity, "conductivity", 0.1)?;
+
+        let lifetime = match &self.lifetime {
+            Some(lifetime) => {
+                let (min, max) = *lifetime.get_ref();
+                if min == 0 || min > max {
+                    return invalid(
+                        source,
+                        lifetime.span(),
+                        format!(
+                            "lifetime of `{name}` has to be a range [min, max] with 0 < min <= max"
+                        ),
+                    );
+                }
+                Some((min, max))
+            }
+            None => None,
+        };
+
+        Ok(MaterialDef {
+            name: name.clone(),
+            color,
+            density,
+            state: self.state,
+            behaviour: self
+                .behaviour
+                .get_ref()
+                .build(source, self.behaviour.span())?,
+            flammability,
+            lifetime,
+            temperature: self.temperature.unwrap_or(AMBIENT_TEMPERATURE),
+            conductivity,
+            heated: None,
+            cooled: None,
+            emits: None,
+            reactions: vec![],
+        })
+    }
+}
+
+impl ReactionEntry {
+    fn resolve(&self, source: &str, registry: &MaterialRegistry) -> eyre::Result<Reaction> {
+        let find = |name: &Spanned<String>| find_material(name, source, registry);
+        let product = |name: &Option<Spanned<String>>| match name {
+            None => Ok(Product::Unchanged),
+            Some(name) if name.get_ref() == EMPTY => Ok(Product::Empty),
+            Some(name) => find(name).map(Product::Material),
+        };
+
+        let chance = fraction(source, &self.chance, "chance of a reaction", 1.0)?;
+        let reaction = Reaction {
+            with: find(&self.with)?,
+            chance,
+            becomes: product(&self.becomes)?,
+            other_becomes: product(&self.other_becomes)?,
+        };
+        if reaction.becomes == Product::Unchanged && reaction.other_becomes == Product::Unchanged {
+            return invalid(
+                source,
+                self.with.span(),
+                "a reaction needs `becomes`, `other_becomes` or both".to_string(),
+            );
+        }
+        Ok(reaction)
+    }
+}
+
+fn parse_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |range: Range<usize>| u8::from_str_radix(&hex[range], 16).ok();
+    Some((channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WATER: &str = r##"
+[[material]]
+name = "water"
+color = "#2850dc"
+density = 1000.0
+state = "liquid"
+behaviour = "liquid"
+"##;
+
+    #[test]
+    fn test_default_materials_are_valid() {
+        let registry = MaterialRegistry::default();
+        for name in ["sand", "water", "wood", "fire"] {
+            assert!(registry.find(name).is_some(), "{name} is missing");
+        }
+        assert_eq!(registry.find("border"), Some(MaterialId::BORDER));
+        assert!(registry.iter().all(|(id, _)| id != MaterialId::BORDER));
+    }
+
+    #[test]
+    fn test_reactions_are_resolved() {
+        let source = format!(
+            "{WATER}{}",
+            r##"
+[[material]]
+name = "lava"
+color = "#ff4000"
+density = 2500.0
+state = "liquid"
+behaviour = "liquid"
+reactions = [{ with = "water", chance = 0.5, becomes = "stone", other_becomes = "empty" }]
+
+[[material]]
+name = "stone"
+color = "#808080"
+density = 2500.0
+state = "solid"
+behaviour = "static"
+"##
+        );
+        let registry = MaterialRegistry::parse(&source).unwrap();
+
+        let (lava, water, stone) = (
+            registry.find("lava").unwrap(),
+            registry.find("water").unwrap(),
+            registry.find("stone").unwrap(),
+        );
+        let reactions = registry.reactions(lava, water);
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].becomes, Product::Material(stone));
+        assert_eq!(reactions[0].other_becomes, Product::Empty);
+        assert_eq!(reactions[0].chance, 0.5);
+        assert!(registry.reactions(water, lava).is_empty());
+    }
+
+    #[test]
+    fn test_flammable_materials_catch_fire() {
+        let registry = MaterialRegistry::default();
+        let (wood, fire) = (
+            registry.find("wood").unwrap(),
+            registry.find("fire").unwrap(),
+        );
+
+        let reactions = registry.reactions(wood, fire);
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].becomes, Product::Material(fire));
+    }
+
+    #[test]
+    fn test_phase_changes_are_resolved() {
+        let registry = MaterialRegistry::default();
+        let (water, ice, steam) = (
+            registry.find("water").unwrap(),
+            registry.find("ice").unwrap(),
+            registry.find("steam").unwrap(),
+        );
+
+        let water = registry.get(water);
+        assert_eq!(
+            water.heated,
+            Some(PhaseChange {
+                temperature: 100.0,
+                becomes: steam
+            })
+        );
+        assert_eq!(
+            water.cooled,
+            Some(PhaseChange {
+                temperature: 0.0,
+                becomes: ice
+            })
+        );
+    }
+
+    #[test]
+    fn test_overlapping_phase_changes_report_line() {
+        let source = format!(
+            "{WATER}{}",
+            r#"heated = { above = 0.0, becomes = "water" }
+cooled = { below = 10.0, becomes = "water" }
+"#
+        );
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 3:"), "{error}");
+    }
+
+    #[test]
+    fn test_emissions_are_resolved() {
+        let registry = MaterialRegistry::default();
+        let (fire, smoke) = (
+            registry.find("fire").unwrap(),
+            registry.find("smoke").unwrap(),
+        );
+
+        let emits = registry.get(fire).emits.unwrap();
+        assert_eq!(emits.material, smoke);
+        assert!(emits.chance > 0.0);
+    }
+
+    #[test]
+    fn test_behaviour_parameters_are_parsed() {
+        let source = WATER.replace(
+            "behaviour = \"liquid\"",
+            "behaviour = { kind = \"powder\", slip = 0.25 }",
+        );
+        let registry = MaterialRegistry::parse(&source).unwrap();
+
+        let water = registry.get(registry.find("water").unwrap());
+        assert_eq!(format!("{:?}", water.behaviour), "Powder { slip: 0.25 }");
+    }
+
+    #[test]
+    fn test_parameters_of_other_behaviour_report_line() {
+        let source = WATER.replace(
+            "behaviour = \"liquid\"",
+            "behaviour = { kind = \"liquid\", slip = 0.25 }",
+        );
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 7:"), "{error}");
+    }
+
+    #[test]
+    fn test_invalid_viscosity_reports_line() {
+        let source = WATER.replace(
+            "behaviour = \"liquid\"",
+            "behaviour = { kind = \"liquid\", viscosity = 2.0 }",
+        );
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 7:"), "{error}");
+    }
+
+    #[test]
+    fn test_reaction_without_products_reports_line() {
+        let source = format!("{WATER}reactions = [{{ with = \"water\", chance = 0.1 }}]\n");
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 8:"), "{error}");
+    }
+
+    #[test]
+    fn test_empty_is_reserved() {
+        let source = WATER.replace("\"water\"", "\"empty\"");
+        assert!(MaterialRegistry::parse(&source).is_err());
+    }
+
+    #[test]
+    fn test_border_is_reserved() {
+        for property in [
+            "reactions = [{ with = \"water\", becomes = \"border\" }]",
+            "heated = { above = 100.0, becomes = \"border\" }",
+            "emits = { material = \"border\", chance = 0.1 }",
+        ] {
+            let source = format!("{WATER}{property}\n");
+            let error = MaterialRegistry::parse(&source).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "line 8: `border` is reserved for the edges of the world"
+            );
+        }
+    }
+
+    #[test]
+    fn test_syntax_error_reports_line() {
+        let source = format!("{WATER}\n[[material]]\nname = \n");
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 10:"), "{error}");
+    }
+
+    #[test]
+    fn test_unknown_field_reports_line() {
+        let source = WATER.replace("density", "dnsity");
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 5:"), "{error}");
+    }
+
+    #[test]
+    fn test_unknown_reaction_reports_line() {
+        let source = format!("{WATER}reactions = [{{ with = \"fire\", becomes = \"steam\" }}]\n");
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert_eq!(error.to_string(), "line 8: unknown material `fire`");
+    }
+
+    #[test]
+    fn test_duplicate_name_reports_line() {
+        let source = format!("{WATER}{WATER}");
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 10:"), "{error}");
+    }
+
+    #[test]
+    fn test_invalid_color_reports_line() {
+        let source = WATER.replace("#2850dc", "blue");
+        let error = MaterialRegistry::parse(&source).unwrap_err();
+        assert!(error.to_string().starts_with("line 4:"), "{error}");
+    }
+}
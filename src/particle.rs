@@ -1,106 +1,138 @@
-use std::collections::HashMap;
+use crate::{
+    coord::{Direction, Vec2},
+    grid::Neighbourhood,
+    material::{MaterialId, MaterialRegistry, StateOfMatter},
+};
+use color_eyre::eyre;
+use rand::{Rng, rngs::StdRng};
 
-use crate::coord::Direction;
-use color_eyre::eyre::{self, Ok};
-use rand;
+/// The random number generator a simulation draws every random decision from, so a simulation
+/// started from the same seed always plays out the same way.
+pub type SimulationRng = StdRng;
 
-#[derive(Clone)]
-pub enum Cell {
-    Sand,
-    Wood,
-    Fire,
-    Water,
-    Border,
+/// Temperature of freshly placed cells in degrees celsius.
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+/// A single particle: its material plus the state of this particular instance.
+#[derive(Clone, Debug)]
+pub struct Cell {
+    pub material: MaterialId,
+    /// Remaining ticks until the cell vanishes, `None` if it lives forever.
+    pub lifetime: Option<u16>,
+    /// Temperature in degrees celsius.
+    pub temperature: f32,
+    /// Falling speed in cells per tick.
+    pub velocity: f32,
+    /// Random value picked on creation, used to give every particle a slightly different shade.
+    pub color_seed: u8,
+    /// Number of updates the particle went through since it was created.
+    pub age: u16,
+    /// Tick stamp of the last update. The cell was already updated this tick if it matches the
+    /// clock of the simulation.
+    pub clock: u8,
 }
 
+/// The cell every position outside of the grid is treated as.
+pub static BORDER: Cell = Cell {
+    material: MaterialId::BORDER,
+    lifetime: None,
+    temperature: AMBIENT_TEMPERATURE,
+    velocity: 0.0,
+    color_seed: 0,
+    // the border has been there forever
+    age: u16::MAX,
+    clock: 0,
+};
+
 pub enum Action {
+    /// Nothing to do until a neighbour changes, the cell may fall asleep.
     None,
+    /// Stays in place by chance this tick, but may well move in the next one.
+    Wait,
     Replace(Cell),
-    Move(Direction),
+    /// Move up to the given number of cells towards the direction, stopping in front of the first
+    /// cell that is not empty.
+    Move(Direction, u16),
+    /// Trade places with the neighbouring cell, see [`Cell::can_displace`].
+    Swap(Direction),
+    /// Spawn a fresh particle of the same material in the empty neighbouring cell.
+    Grow(Direction),
     Vanish,
 }
 
 impl Cell {
-    pub fn update(&self, neighbours: HashMap<Direction, &Cell>) -> eyre::Result<Action> {
-        match self {
-            Cell::Sand => self.handle_sand(neighbours),
-            Cell::Wood => self.handle_wood(neighbours),
-            Cell::Fire => self.handle_fire(neighbours),
-            Cell::Border => Ok(Action::None),
-            Cell::Water => self.handle_water(neighbours),
+    /// Constructs a fresh particle of the given material.
+    pub fn new(material: MaterialId, materials: &MaterialRegistry, rng: &mut impl Rng) -> Self {
+        let definition = materials.get(material);
+        Self {
+            material,
+            lifetime: definition
+                .lifetime
+                .map(|(min, max)| rng.random_range(min..=max)),
+            temperature: definition.temperature,
+            velocity: 0.0,
+            color_seed: rng.random(),
+            age: 0,
+            clock: 0,
         }
     }
 
-    fn handle_sand(&self, neighbours: HashMap<Direction, &Cell>) -> eyre::Result<Action> {
-        // self is of type Sand
-
-        if !neighbours.contains_key(&Direction::Down) {
-            return Ok(Action::Move(Direction::Down));
-        }
+    pub fn is_border(&self) -> bool {
+        self.material == MaterialId::BORDER
+    }
 
-        let (first, second) = if rand::random_bool(0.5) {
-            (Direction::DownRight, Direction::DownLeft)
-        } else {
-            (Direction::DownLeft, Direction::DownRight)
-        };
-        if !neighbours.contains_key(&first) {
-            return Ok(Action::Move(first));
-        };
-        if !neighbours.contains_key(&second) {
-            return Ok(Action::Move(second));
+    /// Whether this cell may trade places with `other`, which lies in `direction`.
+    ///
+    /// Heavier cells sink through lighter fluids and lighter cells rise through heavier ones.
+    /// Sideways, only gases give way to anything heavier.
+    pub fn can_displace(
+        &self,
+        other: &Cell,
+        direction: Direction,
+        materials: &MaterialRegistry,
+    ) -> bool {
+        let other_definition = materials.get(other.material);
+        if !other_definition.state.is_fluid() {
+            return false;
         }
-        Ok(Action::None)
-    }
-    fn handle_fire(&self, _neighbours: HashMap<Direction, &Cell>) -> eyre::Result<Action> {
-        Ok(Action::Vanish)
-    }
-    fn handle_wood(&self, neighbours: HashMap<Direction, &Cell>) -> eyre::Result<Action> {
-        let filer: Vec<()> = neighbours
-            .iter()
-            .filter_map(|(_direction, cell)| {
-                if matches!(cell, Cell::Fire) {
-                    return Some(());
-                }
-                None
-            })
-            .collect();
-        if !filer.is_empty() {
-            return Ok(Action::Replace(Cell::Fire));
+        let (density, other_density) = (
+            materials.get(self.material).density,
+            other_definition.density,
+        );
+        match Vec2::from(direction).y {
+            1 => density > other_density,
+            -1 => density < other_density,
+            _ => other_definition.state == StateOfMatter::Gas && density > other_density,
         }
-        Ok(Action::None)
     }
 
-    fn handle_water(&self, neighbours: HashMap<Direction, &Cell>) -> eyre::Result<Action> {
-        if !neighbours.contains_key(&Direction::Down) {
-            return Ok(Action::Move(Direction::Down));
-        }
-
-        let (first, second) = if rand::random_bool(0.5) {
-            (Direction::DownRight, Direction::DownLeft)
-        } else {
-            (Direction::DownLeft, Direction::DownRight)
-        };
-        if !neighbours.contains_key(&first) {
-            return Ok(Action::Move(first));
-        };
-        if !neighbours.contains_key(&second) {
-            return Ok(Action::Move(second));
+    /// Returns the action that gets this cell into the neighbouring cell in `direction`, if any.
+    ///
+    /// Falling cells move as far as their velocity carries them.
+    pub fn try_move(&self, neighbours: &Neighbourhood, direction: Direction) -> Option<Action> {
+        match neighbours.get(direction) {
+            None if direction == Direction::Down => {
+                Some(Action::Move(direction, (self.velocity as u16).max(1)))
+            }
+            None => Some(Action::Move(direction, 1)),
+            Some(other) if self.can_displace(other, direction, neighbours.materials()) => {
+                Some(Action::Swap(direction))
+            }
+            Some(_) => None,
         }
+    }
 
-        if neighbours.contains_key(&Direction::Up) {
-            return Ok(Action::None);
-        }
-        let (first, second) = if rand::random_bool(0.5) {
-            (Direction::Right, Direction::Left)
-        } else {
-            (Direction::Left, Direction::Right)
-        };
-        if !neighbours.contains_key(&first) {
-            return Ok(Action::Move(first));
-        };
-        if !neighbours.contains_key(&second) {
-            return Ok(Action::Move(second));
-        }
-        Ok(Action::None)
+    /// Decides what this cell does this tick, see
+    /// [`MaterialBehaviour`](crate::behaviour::MaterialBehaviour).
+    pub fn update(
+        &self,
+        neighbours: &Neighbourhood,
+        rng: &mut SimulationRng,
+    ) -> eyre::Result<Action> {
+        neighbours
+            .materials()
+            .get(self.material)
+            .behaviour
+            .update(self, neighbours, rng)
     }
 }
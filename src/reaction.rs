@@ -0,0 +1,76 @@
+use crate::material::{MaterialDef, MaterialId};
+
+/// What a particle turns into when a [`Reaction`] takes place.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Product {
+    /// The particle stays as it is.
+    Unchanged,
+    /// The particle disappears.
+    Empty,
+    /// The particle is replaced by a fresh particle of the material.
+    Material(MaterialId),
+}
+
+/// A particle touching a particle of `with` turns into `becomes`, and the particle of `with` turns
+/// into `other_becomes`, with a chance of `chance` per tick.
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    pub with: MaterialId,
+    pub chance: f32,
+    pub becomes: Product,
+    pub other_becomes: Product,
+}
+
+/// Lookup of all reactions between two materials.
+#[derive(Clone, Debug, Default)]
+pub struct ReactionTable {
+    materials: usize,
+    /// Reactions of material `a` with material `b` are stored at `a * materials + b`.
+    reactions: Vec<Vec<Reaction>>,
+}
+
+impl ReactionTable {
+    /// Collects the reactions declared by `materials`, indexed by [`MaterialId`].
+    ///
+    /// Flammable materials additionally catch fire from every material whose behaviour
+    /// [burns](crate::behaviour::MaterialBehaviour::burns), with their flammability as chance.
+    pub fn new(materials: &[MaterialDef]) -> Self {
+        let len = materials.len();
+        let mut table = Self {
+            materials: len,
+            reactions: vec![vec![]; len * len],
+        };
+        let fires: Vec<MaterialId> = materials
+            .iter()
+            .enumerate()
+            .filter(|(_, material)| material.behaviour.burns())
+            .map(|(idx, _)| MaterialId::from_index(idx))
+            .collect();
+
+        for (idx, material) in materials.iter().enumerate() {
+            let reactions = &mut table.reactions[idx * len..(idx + 1) * len];
+            for reaction in &material.reactions {
+                reactions[reaction.with.index()].push(reaction.clone());
+            }
+            if material.flammability > 0.0 {
+                for fire in &fires {
+                    reactions[fire.index()].push(Reaction {
+                        with: *fire,
+                        chance: material.flammability,
+                        becomes: Product::Material(*fire),
+                        other_becomes: Product::Unchanged,
+                    });
+                }
+            }
+        }
+        table
+    }
+
+    /// All reactions of `material` with a neighbouring particle of `with`.
+    pub fn get(&self, material: MaterialId, with: MaterialId) -> &[Reaction] {
+        self.reactions
+            .get(material.index() * self.materials + with.index())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,212 @@
+//! Timelapses of the world.
+//!
+//! A [`Recorder`] keeps a picture of the world every few ticks and writes them as an animated GIF,
+//! in builds with the `gif` feature, or as numbered PPM frames.
+
+use std::{fs, path::Path};
+
+use color_eyre::eyre::{self, WrapErr};
+
+use crate::{image::Image, simulation_widget::Simulation};
+
+/// Ticks between two frames unless another interval is given.
+pub const DEFAULT_EVERY: u32 = 5;
+/// Frames a recording holds at most unless another cap is given.
+pub const DEFAULT_MAX_FRAMES: usize = 300;
+
+/// Captures the world every few ticks, up to a maximum number of frames.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    /// Ticks between two frames.
+    every: u32,
+    max_frames: usize,
+    frames: Vec<Image>,
+    /// Ticks since the last frame was captured.
+    since_capture: u32,
+}
+
+impl Recorder {
+    /// Constructs a recorder that keeps a frame every `every` ticks, up to `max_frames` of them.
+    pub fn new(every: u32, max_frames: usize) -> Self {
+        Self {
+            every: every.max(1),
+            max_frames,
+            frames: vec![],
+            since_capture: 0,
+        }
+    }
+
+    pub fn frames(&self) -> &[Image] {
+        &self.frames
+    }
+
+    pub fn max_frames(&self) -> usize {
+        self.max_frames
+    }
+
+    /// Whether the recording reached its maximum number of frames.
+    pub fn is_full(&self) -> bool {
+        self.frames.len() >= self.max_frames
+    }
+
+    /// Keeps a picture of `simulation` as it is now, unless the recording is full.
+    pub fn capture(&mut self, simulation: &Simulation) {
+        if !self.is_full() {
+            self.frames.push(simulation.snapshot());
+        }
+        self.since_capture = 0;
+    }
+
+    /// Counts a simulation step and captures `simulation` if a frame is due.
+    pub fn step(&mut self, simulation: &Simulation) {
+        self.since_capture += 1;
+        if self.since_capture >= self.every {
+            self.capture(simulation);
+        }
+    }
+
+    /// Writes the recording with `scale` pixels per cell. Paths ending in `.gif` get an animated
+    /// GIF that plays at the normal speed of the simulation, any other path is a directory that is
+    /// filled with the frames `frame_0000.ppm`, `frame_0001.ppm` and so on.
+    pub fn save(&self, path: &Path, scale: u32) -> eyre::Result<()> {
+        let is_gif = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+        if is_gif {
+            #[cfg(feature = "gif")]
+            return self
+                .write_gif(path, scale)
+                .wrap_err_with(|| format!("failed to write {}", path.display()));
+            #[cfg(not(feature = "gif"))]
+            eyre::bail!(
+                "can't write {}, this build has no GIF support, build with `--features gif` or \
+                 save PPM frames to a directory",
+                path.display()
+            );
+        }
+        fs::create_dir_all(path)
+            .wrap_err_with(|| format!("failed to create {}", path.display()))?;
+        for (idx, frame) in self.frames.iter().enumerate() {
+            frame
+                .scaled(scale)
+                .save(&path.join(format!("frame_{idx:04}.ppm")))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "gif")]
+    fn write_gif(&self, path: &Path, scale: u32) -> eyre::Result<()> {
+        let frames: Vec<Image> = self
+            .frames
+            .iter()
+            .map(|frame| frame.scaled(scale))
+            .collect();
+        // the world may have been resized while recording, the animation fits the largest frame
+        let extent = |size: fn(&Image) -> u32| {
+            let largest = frames.iter().map(size).max().unwrap_or(1).max(1);
+            u16::try_from(largest).map_err(|_| eyre::eyre!("frames are too large for a GIF"))
+        };
+        let (width, height) = (extent(Image::width)?, extent(Image::height)?);
+        let file = fs::File::create(path)?;
+        let mut encoder = gif::Encoder::new(std::io::BufWriter::new(file), width, height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        // GIF delays are in hundredths of a second
+        let delay = (self.every as f64 * 100.0 / crate::event::TICK_FPS)
+            .round()
+            .max(2.0) as u16;
+        for image in &frames {
+            let mut frame =
+                gif::Frame::from_rgb(image.width() as u16, image.height() as u16, &image.bytes());
+            frame.delay = delay;
+            encoder.write_frame(&frame)?;
+        }
+        std::io::Write::flush(&mut encoder.into_inner()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{material::MaterialRegistry, simulation_widget::MaterialCanvas};
+
+    use super::*;
+
+    fn avalanche() -> Simulation {
+        let materials = MaterialRegistry::default();
+        let sand = materials.find("sand").unwrap();
+        let mut simulation = Simulation::with_seed(materials, 0);
+        simulation.set_world_size(6, 6);
+        simulation
+            .set_pixels(&[(2i16, 0).into(), (3i16, 0).into()], sand)
+            .unwrap();
+        simulation
+    }
+
+    #[test]
+    fn test_captures_every_few_ticks_up_to_cap() {
+        let mut simulation = avalanche();
+        let mut recorder = Recorder::new(3, 4);
+        recorder.capture(&simulation);
+        for _ in 0..7 {
+            simulation.handle_ticks().unwrap();
+            recorder.step(&simulation);
+        }
+        assert_eq!(recorder.frames().len(), 3);
+        assert_ne!(recorder.frames()[0], recorder.frames()[1]);
+
+        for _ in 0..20 {
+            recorder.step(&simulation);
+        }
+        assert!(recorder.is_full());
+        assert_eq!(recorder.frames().len(), 4);
+    }
+
+    #[test]
+    fn test_save_numbered_frames() {
+        let simulation = avalanche();
+        let mut recorder = Recorder::new(1, 10);
+        recorder.capture(&simulation);
+        recorder.step(&simulation);
+        let name = format!("sandbox-frames-{}", std::process::id());
+        let directory = std::env::temp_dir().join(name);
+
+        recorder.save(&directory, 2).unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["frame_0000.ppm", "frame_0001.ppm"]);
+        let bytes = fs::read(directory.join("frame_0001.ppm")).unwrap();
+        let frame = Image::read_ppm(&bytes).unwrap();
+        assert_eq!((frame.width(), frame.height()), (12, 12));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn test_save_gif() {
+        let mut simulation = avalanche();
+        let mut recorder = Recorder::new(1, 10);
+        recorder.capture(&simulation);
+        for _ in 0..2 {
+            simulation.handle_ticks().unwrap();
+            recorder.step(&simulation);
+        }
+        let name = format!("sandbox-recording-{}.gif", std::process::id());
+        let path = std::env::temp_dir().join(name);
+
+        recorder.save(&path, 3).unwrap();
+
+        let file = fs::File::open(&path).unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(file).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (18, 18));
+        let mut frames = 0;
+        while decoder.read_next_frame().unwrap().is_some() {
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+        fs::remove_file(path).unwrap();
+    }
+}
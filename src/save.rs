@@ -0,0 +1,432 @@
+//! Binary save files of a whole world.
+//!
+//! All numbers are little endian. A file starts with a header:
+//!
+//! | field      | type     | content                                                 |
+//! |------------|----------|---------------------------------------------------------|
+//! | magic      | 8 bytes  | `SANDBOX\0`                                             |
+//! | version    | `u16`    | [`VERSION`] for files written by this build             |
+//! | width      | `u16`    | extent of the world in cells, 1 to [`MAX_EXTENT`]       |
+//! | height     | `u16`    |                                                         |
+//! | boundaries | 4 × `u8` | top, right, bottom, left: 0 wall, 1 void, 2 wrap        |
+//! | materials  | `u16`    | number of entries in the material table                 |
+//! | table      | entries  | `u8` length followed by the UTF-8 name of each material |
+//!
+//! Materials are stored by name, so a file stays readable when the material file changes the
+//! order of its materials. Index 0 stands for an empty cell, index `n` for entry `n - 1` of the
+//! table.
+//!
+//! The header is followed by the cells in row-major order, run-length encoded as pairs of a `u16`
+//! run length and the `u16` material index of the run. After the runs comes the state of every
+//! particle, again in row-major order:
+//!
+//! | field       | type  | content                                            |
+//! |-------------|-------|----------------------------------------------------|
+//! | temperature | `f32` | degrees celsius                                    |
+//! | lifetime    | `u16` | remaining ticks, 0 for particles that live forever |
+//! | velocity    | `f32` | falling speed                                      |
+//! | color_seed  | `u8`  | shade of the particle                              |
+//! | age         | `u16` | updates the particle went through                  |
+//!
+//! Readers of later versions are expected to keep loading the files of earlier ones.
+
+use std::io::{Read, Write};
+
+use color_eyre::eyre::{self, WrapErr};
+
+use crate::{
+    grid::{Boundaries, Boundary, Grid, MAX_EXTENT},
+    material::{MaterialId, MaterialRegistry},
+    particle::{Cell, SimulationRng},
+};
+
+/// Start of every save file.
+pub const MAGIC: &[u8; 8] = b"SANDBOX\0";
+/// Version of the files written by [`write()`].
+pub const VERSION: u16 = 1;
+
+/// Writes `grid` in the current version of the format.
+pub fn write(
+    grid: &Grid,
+    materials: &MaterialRegistry,
+    writer: &mut impl Write,
+) -> eyre::Result<()> {
+    if grid.width() == 0 || grid.height() == 0 {
+        eyre::bail!("there is no world to save yet");
+    }
+    writer.write_all(MAGIC)?;
+    write_u16(writer, VERSION)?;
+    write_u16(writer, grid.width())?;
+    write_u16(writer, grid.height())?;
+    let boundaries = grid.boundaries();
+    for boundary in [
+        boundaries.top,
+        boundaries.right,
+        boundaries.bottom,
+        boundaries.left,
+    ] {
+        let code = match boundary {
+            Boundary::Wall => 0,
+            Boundary::Void => 1,
+            Boundary::Wrap => 2,
+        };
+        writer.write_all(&[code])?;
+    }
+
+    let table: Vec<MaterialId> = materials.iter().map(|(id, _)| id).collect();
+    write_u16(writer, table.len() as u16)?;
+    for (_, material) in materials.iter() {
+        let name = material.name.as_bytes();
+        let length = u8::try_from(name.len())
+            .map_err(|_| eyre::eyre!("material name `{}` is too long", material.name))?;
+        writer.write_all(&[length])?;
+        writer.write_all(name)?;
+    }
+
+    let index = |cell: Option<&Cell>| match cell {
+        Some(cell) => table
+            .iter()
+            .position(|id| *id == cell.material)
+            .map_or(0, |idx| idx as u16 + 1),
+        None => 0,
+    };
+    let mut run: Option<(u16, u16)> = None;
+    for y in 0..grid.height() as i16 {
+        for x in 0..grid.width() as i16 {
+            let material = index(grid.get(&(x, y).into()));
+            run = match run {
+                Some((length, current)) if current == material && length < u16::MAX => {
+                    Some((length + 1, current))
+                }
+                Some((length, current)) => {
+                    write_u16(writer, length)?;
+                    write_u16(writer, current)?;
+                    Some((1, material))
+                }
+                None => Some((1, material)),
+            };
+        }
+    }
+    if let Some((length, current)) = run {
+        write_u16(writer, length)?;
+        write_u16(writer, current)?;
+    }
+
+    for (_, cell) in grid.iter() {
+        writer.write_all(&cell.temperature.to_le_bytes())?;
+        write_u16(writer, cell.lifetime.unwrap_or(0))?;
+        writer.write_all(&cell.velocity.to_le_bytes())?;
+        writer.write_all(&[cell.color_seed])?;
+        write_u16(writer, cell.age)?;
+    }
+    Ok(())
+}
+
+/// Reads a grid written by any version of the format up to [`VERSION`].
+pub fn read(
+    reader: &mut impl Read,
+    materials: &MaterialRegistry,
+    rng: &mut SimulationRng,
+) -> eyre::Result<Grid> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).wrap_err("not a save file")?;
+    if &magic != MAGIC {
+        eyre::bail!("not a save file");
+    }
+    let version = read_u16(reader)?;
+    if !(1..=VERSION).contains(&version) {
+        eyre::bail!("unsupported save file version {version}, expected 1 to {VERSION}");
+    }
+    let (width, height) = (read_u16(reader)?, read_u16(reader)?);
+    if !(1..=MAX_EXTENT).contains(&width) || !(1..=MAX_EXTENT).contains(&height) {
+        eyre::bail!("a {width}x{height} world is not 1 to {MAX_EXTENT} cells on each side");
+    }
+    let mut codes = [0; 4];
+    reader
+        .read_exact(&mut codes)
+        .wrap_err("save file ends early")?;
+    let boundary = |code: u8| match code {
+        0 => Ok(Boundary::Wall),
+        1 => Ok(Boundary::Void),
+        2 => Ok(Boundary::Wrap),
+        _ => Err(eyre::eyre!("unknown boundary {code}")),
+    };
+    let boundaries = Boundaries {
+        top: boundary(codes[0])?,
+        right: boundary(codes[1])?,
+        bottom: boundary(codes[2])?,
+        left: boundary(codes[3])?,
+    };
+
+    let mut table = vec![];
+    for _ in 0..read_u16(reader)? {
+        let mut name = vec![0; read_u8(reader)? as usize];
+        reader
+            .read_exact(&mut name)
+            .wrap_err("save file ends early")?;
+        let name = String::from_utf8(name).wrap_err("material name is not UTF-8")?;
+        // the border is left out, it can't be placed in the world
+        let material = materials
+            .iter()
+            .find(|(_, material)| material.name == name)
+            .map(|(id, _)| id)
+            .ok_or_else(|| eyre::eyre!("unknown material `{name}`"))?;
+        table.push(material);
+    }
+
+    // the runs only grow as far as the file goes, nothing is allocated for the claimed extent
+    // before they are known to cover it
+    let total = width as usize * height as usize;
+    let mut runs: Vec<(usize, Option<MaterialId>)> = vec![];
+    let mut covered = 0;
+    while covered < total {
+        let (length, index) = (read_u16(reader)? as usize, read_u16(reader)? as usize);
+        if length == 0 || covered + length > total {
+            eyre::bail!("cell runs don't add up to the {width}x{height} world");
+        }
+        let material = match index {
+            0 => None,
+            _ => Some(
+                *table
+                    .get(index - 1)
+                    .ok_or_else(|| eyre::eyre!("material {index} is not in the table"))?,
+            ),
+        };
+        runs.push((length, material));
+        covered += length;
+    }
+
+    let mut grid = Grid::try_new(width, height)?;
+    grid.set_boundaries(boundaries);
+    let mut idx = 0;
+    for (length, material) in runs {
+        let Some(material) = material else {
+            idx += length;
+            continue;
+        };
+        for _ in 0..length {
+            let mut cell = Cell::new(material, materials, rng);
+            cell.temperature = read_f32(reader)?;
+            cell.lifetime = match read_u16(reader)? {
+                0 => None,
+                lifetime => Some(lifetime),
+            };
+            cell.velocity = read_f32(reader)?;
+            cell.color_seed = read_u8(reader)?;
+            cell.age = read_u16(reader)?;
+            let pos = ((idx % width as usize) as i16, (idx / width as usize) as i16);
+            grid.insert(&pos.into(), cell)?;
+            idx += 1;
+        }
+    }
+    Ok(grid)
+}
+
+fn write_u16(writer: &mut impl Write, value: u16) -> eyre::Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u8(reader: &mut impl Read) -> eyre::Result<u8> {
+    let mut bytes = [0; 1];
+    reader
+        .read_exact(&mut bytes)
+        .wrap_err("save file ends early")?;
+    Ok(bytes[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> eyre::Result<u16> {
+    let mut bytes = [0; 2];
+    reader
+        .read_exact(&mut bytes)
+        .wrap_err("save file ends early")?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> eyre::Result<f32> {
+    let mut bytes = [0; 4];
+    reader
+        .read_exact(&mut bytes)
+        .wrap_err("save file ends early")?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn rng() -> SimulationRng {
+        SimulationRng::seed_from_u64(0)
+    }
+
+    #[test]
+    fn test_round_trip_keeps_every_cell() {
+        let materials = MaterialRegistry::default();
+        let mut grid = Grid::new(300, 4);
+        grid.set_boundaries(Boundaries {
+            bottom: Boundary::Void,
+            left: Boundary::Wrap,
+            right: Boundary::Wrap,
+            ..Boundaries::default()
+        });
+        let sand = materials.find("sand").unwrap();
+        let fire = materials.find("fire").unwrap();
+        for x in 0..300i16 {
+            let mut cell = Cell::new(sand, &materials, &mut rng());
+            cell.temperature = x as f32;
+            cell.velocity = 1.5;
+            grid.insert(&(x, 3).into(), cell).unwrap();
+        }
+        grid.insert(&(7i16, 0).into(), Cell::new(fire, &materials, &mut rng()))
+            .unwrap();
+
+        let mut bytes = vec![];
+        write(&grid, &materials, &mut bytes).unwrap();
+        let loaded = read(&mut bytes.as_slice(), &materials, &mut rng()).unwrap();
+
+        assert_eq!((loaded.width(), loaded.height()), (300, 4));
+        assert_eq!(loaded.boundaries(), grid.boundaries());
+        let cells = |grid: &Grid| -> Vec<String> {
+            grid.iter()
+                .map(|(pos, cell)| format!("{pos:?} {cell:?}"))
+                .collect()
+        };
+        assert_eq!(cells(&loaded), cells(&grid));
+    }
+
+    #[test]
+    fn test_loads_version_1_files() {
+        // spelled out byte for byte rather than built from `VERSION`, so files written by the
+        // first version keep being checked once the format moves on
+        let bytes = [
+            b"SANDBOX\0".as_slice(),
+            // version 1, 3x2 cells
+            &[1, 0, 3, 0, 2, 0],
+            // top wall, right void, bottom wall, left wrap
+            &[0, 1, 0, 2],
+            // two materials, water and sand
+            &[2, 0, 5],
+            b"water",
+            &[4],
+            b"sand",
+            // one sand, two empty, two water, one empty
+            &[1, 0, 2, 0, 2, 0, 0, 0, 2, 0, 1, 0, 1, 0, 0, 0],
+            // the sand: 20 °C, lives forever, falls at 0.5, shade 7 and 3 updates old
+            &[0, 0, 0xa0, 0x41, 0, 0, 0, 0, 0, 0x3f, 7, 3, 0],
+            // the water: 5 °C and at rest
+            &[0, 0, 0xa0, 0x40, 0, 0, 0, 0, 0, 0, 1, 0, 0],
+            &[0, 0, 0xa0, 0x40, 0, 0, 0, 0, 0, 0, 2, 0, 0],
+        ]
+        .concat();
+        let materials = MaterialRegistry::default();
+
+        let grid = read(&mut bytes.as_slice(), &materials, &mut rng()).unwrap();
+
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(
+            grid.boundaries(),
+            Boundaries {
+                top: Boundary::Wall,
+                right: Boundary::Void,
+                bottom: Boundary::Wall,
+                left: Boundary::Wrap,
+            }
+        );
+        let (sand, water) = (materials.find("sand"), materials.find("water"));
+        let cells: Vec<_> = grid
+            .iter()
+            .map(|(pos, cell)| {
+                let (x, y): (i16, i16) = pos.into();
+                (x, y, Some(cell.material), cell.temperature, cell.color_seed)
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            [
+                (0, 0, sand, 20.0, 7),
+                (0, 1, water, 5.0, 1),
+                (1, 1, water, 5.0, 2),
+            ]
+        );
+        let sand = grid.get(&(0i16, 0).into()).unwrap();
+        assert_eq!((sand.lifetime, sand.velocity, sand.age), (None, 0.5, 3));
+    }
+
+    /// The header of a `width` by `height` world of walls with sand as its only material.
+    fn header(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, width, height].map(u16::to_le_bytes).concat());
+        bytes.extend([0; 4]);
+        bytes.extend(1u16.to_le_bytes());
+        bytes.push(4);
+        bytes.extend(b"sand");
+        bytes
+    }
+
+    #[test]
+    fn test_rejects_impossible_extent() {
+        let materials = MaterialRegistry::default();
+        let error = |bytes: Vec<u8>| {
+            let grid = read(&mut bytes.as_slice(), &materials, &mut rng());
+            grid.err().unwrap().to_string()
+        };
+
+        assert_eq!(
+            error(header(40000, 10)),
+            "a 40000x10 world is not 1 to 32751 cells on each side"
+        );
+        assert_eq!(
+            error(header(0, 10)),
+            "a 0x10 world is not 1 to 32751 cells on each side"
+        );
+
+        // claims a billion cells, but only describes one
+        let mut bytes = header(MAX_EXTENT, MAX_EXTENT);
+        bytes.extend([1u16, 1].map(u16::to_le_bytes).concat());
+        assert_eq!(error(bytes), "save file ends early");
+
+        let mut bytes = header(2, 2);
+        bytes.extend([3u16, 0, 3, 1].map(u16::to_le_bytes).concat());
+        assert_eq!(error(bytes), "cell runs don't add up to the 2x2 world");
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let materials = MaterialRegistry::default();
+
+        let error = read(&mut b"[[material]]".as_slice(), &materials, &mut rng())
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "not a save file");
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(9u16.to_le_bytes());
+        let error = read(&mut bytes.as_slice(), &materials, &mut rng())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unsupported save file version 9, expected 1 to 1"
+        );
+    }
+
+    #[test]
+    fn test_unknown_material_is_error() {
+        let materials = MaterialRegistry::default();
+        for name in ["cheese", "border"] {
+            let mut bytes = header(1, 1);
+            // replace the table of the header
+            bytes.truncate(bytes.len() - 7);
+            bytes.extend(1u16.to_le_bytes());
+            bytes.push(name.len() as u8);
+            bytes.extend(name.as_bytes());
+
+            let error = read(&mut bytes.as_slice(), &materials, &mut rng())
+                .err()
+                .unwrap();
+            assert_eq!(error.to_string(), format!("unknown material `{name}`"));
+        }
+    }
+}
@@ -1,227 +1,777 @@
-use std::collections::HashMap;
-
-use color_eyre::eyre::{self, Result};
-use crossterm::event::{KeyEvent, MouseEvent};
+use color_eyre::eyre::{self, Result, WrapErr};
+use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use rand::SeedableRng;
 use ratatui::{layout::Position, style::Color};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
 
 use crate::{
-    coord::{self, Vec2},
+    brush::Brush,
+    chunk::{ChunkMap, ChunkStats, DirtyRect},
+    coord::{Direction, Vec2},
     event::AppEvent,
-    particle::{self, Action, Cell},
+    grid::{Boundaries, Boundary, Grid, MAX_EXTENT},
+    image::Image,
+    level::Level,
+    material::{MaterialId, MaterialRegistry},
+    particle::{AMBIENT_TEMPERATURE, Cell, SimulationRng},
+    save,
+    tick::Tick,
     window::Window,
 };
 
-#[derive(Default)]
+/// Share of the temperature difference to the air a cell loses per tick and empty neighbour, scaled
+/// by the conductivity of the cell.
+const AIR_CONDUCTIVITY: f32 = 0.01;
+/// Temperature changes smaller than this, in degrees celsius, let a cell fall asleep.
+const HEAT_EPSILON: f32 = 0.05;
+
+/// How the world adapts when the window it is shown in changes its size.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, clap::ValueEnum)]
+pub enum ResizePolicy {
+    /// The world follows the window, particles beyond its new edges are discarded.
+    #[default]
+    Crop,
+    /// The world keeps its size, parts that don't fit into the window are reached by moving the
+    /// camera.
+    Scroll,
+    /// The world is stretched or squeezed to the new size of the window. Squeezing merges
+    /// neighbouring particles, so some of them are lost.
+    Rescale,
+}
+
+/// What the colour of a cell shows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RenderMode {
+    /// The colour of its material.
+    #[default]
+    Materials,
+    /// Its temperature, from blue for cold to white for glowing hot.
+    Temperature,
+}
+
 pub struct Simulation {
-    src_buffer: HashMap<u32, particle::Cell>,
-    /// hash value is coord since coords seem to be stored as u16
-    /// layout: xxxx xxxx xxxx xxxx yyyy yyyy yyyy yyyy
-    dst_buffer: HashMap<u32, particle::Cell>,
+    grid: Grid,
+    /// Tracks which parts of `grid` need to be updated.
+    chunks: ChunkMap,
+    materials: MaterialRegistry,
+    /// Stamped onto every cell that gets updated, see [`Cell::clock`].
+    clock: u8,
+    /// Seed `rng` was started from.
+    seed: u64,
+    /// Source of every random decision, so the same seed and input always give the same result.
+    rng: SimulationRng,
+    render_mode: RenderMode,
     window: Option<Window>,
+    resize_policy: ResizePolicy,
+    brush: Brush,
+    /// Material the left mouse button paints with, `None` if there are no materials at all.
+    selected: Option<MaterialId>,
+    /// Whether ticks are spread over all cores.
+    #[cfg(feature = "parallel")]
+    parallel: bool,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new(MaterialRegistry::default())
+    }
 }
 
 impl Simulation {
-    pub fn handle_keyboard_event(&mut self, _event: &KeyEvent) -> Result<()> {
+    /// Constructs an empty simulation of the given materials with a random seed.
+    pub fn new(materials: MaterialRegistry) -> Self {
+        Self::with_seed(materials, rand::random())
+    }
+
+    /// Constructs an empty simulation of the given materials whose random decisions all derive
+    /// from `seed`.
+    pub fn with_seed(materials: MaterialRegistry, seed: u64) -> Self {
+        let selected = materials
+            .find("sand")
+            .or_else(|| materials.iter().next().map(|(id, _)| id));
+        Self {
+            grid: Grid::default(),
+            chunks: ChunkMap::default(),
+            materials,
+            clock: 0,
+            seed,
+            rng: SimulationRng::seed_from_u64(seed),
+            render_mode: RenderMode::default(),
+            window: None,
+            resize_policy: ResizePolicy::default(),
+            brush: Brush::default(),
+            selected,
+            #[cfg(feature = "parallel")]
+            parallel: true,
+        }
+    }
+
+    /// The seed the random decisions of the simulation derive from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the random number generator from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = SimulationRng::seed_from_u64(seed);
+    }
+
+    pub fn materials(&self) -> &MaterialRegistry {
+        &self.materials
+    }
+
+    pub fn handle_keyboard_event(&mut self, event: &KeyEvent) -> Result<()> {
+        match event.code {
+            KeyCode::Char('t') => {
+                self.render_mode = match self.render_mode {
+                    RenderMode::Materials => RenderMode::Temperature,
+                    RenderMode::Temperature => RenderMode::Materials,
+                };
+            }
+            // the number keys pick the first ten materials of the palette
+            KeyCode::Char(key @ '0'..='9') => {
+                let index = (key as usize + 9 - '0' as usize) % 10;
+                let material = self.materials.iter().nth(index).map(|(id, _)| id);
+                if let Some(material) = material {
+                    self.select(material);
+                }
+            }
+            KeyCode::Char('[') => self.brush.shrink(),
+            KeyCode::Char(']') => self.brush.grow(),
+            KeyCode::Tab => self.brush.set_shape(self.brush.shape().next()),
+            KeyCode::Char('e') => self.brush.set_eraser(!self.brush.is_eraser()),
+            KeyCode::Char('b') => {
+                // cycles all edges at once, mixed edges start over with walls
+                let boundaries = self.grid.boundaries();
+                let next = match boundaries {
+                    _ if boundaries == Boundaries::all(Boundary::Wall) => Boundary::Void,
+                    _ if boundaries == Boundaries::all(Boundary::Void) => Boundary::Wrap,
+                    _ => Boundary::Wall,
+                };
+                self.set_boundaries(Boundaries::all(next));
+            }
+            _ => {}
+        }
         Ok(())
     }
 
-    pub fn handle_mouse_event(&mut self, event: &MouseEvent) -> Result<()> {
+    /// What lies beyond each edge of the world.
+    pub fn boundaries(&self) -> Boundaries {
+        self.grid.boundaries()
+    }
+
+    /// Changes what lies beyond the edges of the world, particles resting against them start moving
+    /// again.
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) {
+        self.grid.set_boundaries(boundaries);
+        self.wake_all();
+    }
+
+    /// Updates every particle in the next tick, even those resting where nothing changed.
+    pub fn wake_all(&mut self) {
+        self.chunks.wake_all();
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    /// Paints with the mouse, `camera` is the world position shown in the upper left corner of the
+    /// window. The scroll wheel changes the size of the brush.
+    pub fn handle_mouse_event(&mut self, event: &MouseEvent, camera: &Vec2) -> Result<()> {
         match event.kind {
-            crossterm::event::MouseEventKind::Up(button)
-            | crossterm::event::MouseEventKind::Drag(button) => {
-                let cell = match button {
-                    crossterm::event::MouseButton::Left => Cell::Sand,
-                    crossterm::event::MouseButton::Right => Cell::Water,
-                    crossterm::event::MouseButton::Middle => Cell::Fire,
-                };
-                self.flip(&(event.column, event.row).into(), cell)?;
+            MouseEventKind::Down(button) => {
+                self.brush.lift();
+                self.paint(
+                    button,
+                    self.screen_to_world(event.column, event.row, camera),
+                )?;
             }
+            MouseEventKind::Drag(button) => {
+                self.paint(
+                    button,
+                    self.screen_to_world(event.column, event.row, camera),
+                )?;
+            }
+            MouseEventKind::Up(_) => self.brush.lift(),
+            MouseEventKind::ScrollUp => self.brush.grow(),
+            MouseEventKind::ScrollDown => self.brush.shrink(),
             _ => {}
         }
         Ok(())
     }
 
-    fn flip(&mut self, pos: &Vec2, cell: Cell) -> color_eyre::Result<()> {
-        self.src_buffer.entry(pos.try_into()?).or_insert(cell);
+    /// Continues the stroke of the brush to `pos`, with the material of `button` or erasing. The
+    /// left button paints with the selected material.
+    fn paint(&mut self, button: MouseButton, pos: Vec2) -> Result<()> {
+        let material = match button {
+            MouseButton::Left => self.selected,
+            MouseButton::Right => self.materials.find("water"),
+            MouseButton::Middle => self.materials.find("fire"),
+        };
+        let Some(material) = material else {
+            return Ok(());
+        };
+        for centre in self.brush.stroke(pos) {
+            for pos in self.brush.footprint(&centre, &mut self.rng) {
+                if self.brush.is_eraser() {
+                    self.remove_pixel(&pos)?;
+                } else {
+                    self.flip(&pos, material)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The material the left mouse button paints with.
+    pub fn selected(&self) -> Option<MaterialId> {
+        self.selected
+    }
+
+    pub fn select(&mut self, material: MaterialId) {
+        self.selected = Some(material);
+    }
+
+    pub fn brush(&self) -> &Brush {
+        &self.brush
+    }
+
+    pub fn brush_mut(&mut self) -> &mut Brush {
+        &mut self.brush
+    }
+
+    /// The world position under the terminal cell at `column` and `row`.
+    pub fn screen_to_world(&self, column: u16, row: u16, camera: &Vec2) -> Vec2 {
+        let (x, y) = self
+            .window
+            .as_ref()
+            .map_or((0, 0), |window| (window.x, window.y));
+        Vec2 {
+            x: column as i16 - x as i16 + camera.x,
+            y: row as i16 - y as i16 + camera.y,
+        }
+    }
+
+    /// Keeps `camera` within the world, so the window never shows anything beyond its edges. A
+    /// world smaller than the window stays in the upper left corner.
+    pub fn clamp_camera(&self, camera: &Vec2) -> Vec2 {
+        let (width, height) = self
+            .window
+            .as_ref()
+            .map_or((0, 0), |window| (window.width, window.height));
+        let max_x = self.grid.width().saturating_sub(width) as i16;
+        let max_y = self.grid.height().saturating_sub(height) as i16;
+        Vec2 {
+            x: camera.x.clamp(0, max_x),
+            y: camera.y.clamp(0, max_y),
+        }
+    }
+
+    fn flip(&mut self, pos: &Vec2, material: MaterialId) -> color_eyre::Result<()> {
+        // painting outside of the simulation area is not an error, there is just nothing to paint
+        if self.grid.contains(pos) && self.grid.get(pos).is_none() {
+            self.grid
+                .insert(pos, Cell::new(material, &self.materials, &mut self.rng))?;
+            self.chunks.wake(pos);
+        }
         Ok(())
     }
 
     pub fn handle_app_event(&mut self, event: &AppEvent) -> Result<()> {
         match *event {
-            AppEvent::Quit => {}
+            AppEvent::Quit
+            | AppEvent::TogglePause
+            | AppEvent::Step
+            | AppEvent::SpeedUp
+            | AppEvent::SlowDown => {}
         }
         Ok(())
     }
 
-    fn find_cell(pos: Vec2, columns: i16, rows: i16, map: &HashMap<u32, Cell>) -> Option<&Cell> {
-        if pos.x < 0 || pos.y < 0 || pos.x >= columns || pos.y >= rows {
-            return Some(&Cell::Border);
-        }
-        map.get(
-            &pos.try_into()
-                .expect("conversion into u32 should work if pos.x/y is positive"),
-        )
+    /// Looks up `pos` in `grid`, treating everything outside of it as a border.
+    pub fn find_cell(pos: Vec2, grid: &Grid) -> Option<&Cell> {
+        grid.find(&pos)
     }
 
-    fn fill_neighbour<'a>(
-        &'a self,
-        pos: &Vec2,
-        direction: coord::Direction,
-        width: i16,
-        height: i16,
-        map: &mut HashMap<coord::Direction, &'a Cell>,
-    ) {
-        let direction_vec: Vec2 = direction.clone().into();
-        if let Some(cell) = Self::find_cell(pos + direction_vec, width, height, &self.src_buffer) {
-            map.insert(direction, cell);
+    /// Advances the simulation by one step.
+    ///
+    /// Cells are updated in place, bottom row first, so a falling particle never gets to move twice.
+    /// The order only depends on the clock, so together with the seeded random number generator a
+    /// tick is fully deterministic.
+    ///
+    /// Every movement is a swap with an empty cell, which guarantees that each particle ends up in
+    /// exactly one cell: nothing is duplicated and nothing is overwritten.
+    ///
+    /// Only the dirty rectangles of awake chunks are visited, see [`ChunkMap`]. Every change wakes
+    /// the cells around it for the next tick.
+    pub fn handle_ticks(&mut self) -> Result<()> {
+        self.clock = self.clock.wrapping_add(1);
+        self.chunks.begin_tick();
+        self.conduct_heat();
+
+        // chunks on opposite edges of a wrapping world touch, so they can't be updated apart
+        #[cfg(feature = "parallel")]
+        if self.parallel && !self.grid.boundaries().wraps() {
+            return self.update_checkerboard();
         }
+        self.update_rows()
     }
-    pub fn handle_ticks(&mut self) -> Result<()> {
-        self.dst_buffer.clear();
-        let (width, height) = if let Some(window) = &self.window {
-            (window.width as i16, window.height as i16)
-        } else {
-            (i16::MAX, i16::MAX)
+
+    /// Updates all dirty cells row by row, bottom to top.
+    fn update_rows(&mut self) -> Result<()> {
+        let mut tick = Tick {
+            grid: &mut self.grid,
+            materials: &self.materials,
+            rng: &mut self.rng,
+            clock: self.clock,
+            woken: vec![],
         };
-        for (idx, cell) in self.src_buffer.iter() {
-            let pos: Vec2 = (*idx).into();
+        // alternate the horizontal scan direction so no side is favoured
+        let left_to_right = self.clock.is_multiple_of(2);
+        for y in (0..tick.grid.height() as i16).rev() {
+            let mut rects: Vec<_> = self.chunks.dirty_in_row(y).collect();
+            if !left_to_right {
+                rects.reverse();
+            }
+            for rect in rects {
+                let row = DirtyRect {
+                    min_y: y,
+                    max_y: y,
+                    ..rect
+                };
+                self.chunks
+                    .record_updates(&(rect.min_x, y).into(), row.area());
+                for pos in scan(&row, left_to_right) {
+                    tick.update_cell(pos)?;
+                }
+            }
+        }
+        for pos in tick.woken {
+            self.wake(&pos);
+        }
+        Ok(())
+    }
 
-            let mut neighbour_map: HashMap<coord::Direction, &particle::Cell> = HashMap::new();
+    /// Updates the dirty cells chunk by chunk on all cores.
+    ///
+    /// The chunks are split into four checkerboard passes, so no two chunks of the same pass are
+    /// neighbours. The dirty rectangle of each chunk of a pass is copied out together with a margin
+    /// of [`MAX_REACH`] cells, updated on its own and copied back. The copies of a pass never
+    /// overlap, so no particle can be moved by two threads at once.
+    #[cfg(feature = "parallel")]
+    fn update_checkerboard(&mut self) -> Result<()> {
+        use rand::Rng;
+        use rayon::prelude::*;
 
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::Down,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::DownRight,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::Right,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::UpRight,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::Up,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::UpLeft,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::Left,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            self.fill_neighbour(
-                &pos,
-                coord::Direction::DownLeft,
-                width,
-                height,
-                &mut neighbour_map,
-            );
-            if let Ok(action) = cell.update(neighbour_map) {
-                match action {
-                    Action::None => {
-                        self.dst_buffer.insert(*idx, cell.clone());
-                    }
-                    Action::Replace(new_cell) => {
-                        self.dst_buffer.insert(*idx, new_cell);
-                    }
-                    Action::Move(direction) => {
-                        let cell = cell.clone();
-                        let new_pos = &pos + &direction.into();
-
-                        if new_pos.x < width
-                            && new_pos.y < height
-                            && let Ok(new_idx) = new_pos.try_into()
-                        {
-                            self.dst_buffer.insert(new_idx, cell);
-                        } else if let Ok(old_idx) = pos.try_into() {
-                            self.dst_buffer.insert(old_idx, cell);
+        use crate::chunk::MAX_REACH;
+
+        let left_to_right = self.clock.is_multiple_of(2);
+        for phase in [(0, 1), (1, 1), (0, 0), (1, 0)] {
+            let jobs: Vec<_> = self
+                .chunks
+                .awake_in_phase(phase)
+                .into_iter()
+                .map(|dirty| {
+                    let origin = Vec2 {
+                        x: dirty.min_x - MAX_REACH,
+                        y: dirty.min_y - MAX_REACH,
+                    };
+                    let width = (dirty.max_x - dirty.min_x + 1 + 2 * MAX_REACH) as u16;
+                    let height = (dirty.max_y - dirty.min_y + 1 + 2 * MAX_REACH) as u16;
+                    // every chunk draws from a generator of its own, so the result does not depend
+                    // on which thread gets to it first
+                    let seed: u64 = self.rng.random();
+                    (self.grid.region(origin, width, height), dirty, seed)
+                })
+                .collect();
+
+            let clock = self.clock;
+            let materials = &self.materials;
+            let results = jobs
+                .into_par_iter()
+                .map(|(mut region, dirty, seed)| {
+                    let mut rng = SimulationRng::seed_from_u64(seed);
+                    let mut tick = Tick {
+                        grid: &mut region,
+                        materials,
+                        rng: &mut rng,
+                        clock,
+                        woken: vec![],
+                    };
+                    for y in (dirty.min_y..=dirty.max_y).rev() {
+                        let row = DirtyRect {
+                            min_y: y,
+                            max_y: y,
+                            ..dirty
                         };
+                        for pos in scan(&row, left_to_right) {
+                            tick.update_cell(pos)?;
+                        }
                     }
+                    let woken = tick.woken;
+                    Ok((region, dirty, woken))
+                })
+                .collect::<Result<Vec<_>>>()?;
 
-                    Action::Vanish => {
-                        // do nothing
-                    }
+            for (region, dirty, woken) in results {
+                self.grid.paste(&region);
+                self.chunks
+                    .record_updates(&(dirty.min_x, dirty.min_y).into(), dirty.area());
+                for pos in woken {
+                    self.wake(&pos);
                 }
             }
         }
-
-        std::mem::swap(&mut self.src_buffer, &mut self.dst_buffer);
         Ok(())
     }
+
+    /// Wakes `pos` and its neighbours for the next tick, including those across a wrapping edge.
+    fn wake(&mut self, pos: &Vec2) {
+        self.chunks.wake(pos);
+        if !self.grid.boundaries().wraps() {
+            return;
+        }
+        for direction in Direction::ALL {
+            let neighbour = pos + Vec2::from(direction);
+            let wrapped = self.grid.wrap(&neighbour);
+            if wrapped != neighbour {
+                self.chunks.wake(&wrapped);
+            }
+        }
+    }
+
+    /// Whether ticks are spread over all cores, see [`Simulation::set_parallel`].
+    #[cfg(feature = "parallel")]
+    pub fn is_parallel(&self) -> bool {
+        self.parallel
+    }
+
+    /// Switches between updating the chunks on all cores and row by row on the current thread.
+    ///
+    /// Both modes conserve every particle, but they visit the cells in a different order, so the
+    /// same seed plays out differently in each of them.
+    #[cfg(feature = "parallel")]
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    /// Lets heat flow between touching cells and from cells into the surrounding air.
+    ///
+    /// Every pair of neighbours exchanges heat once per tick, proportional to their temperature
+    /// difference and the lower of their conductivities. The border does not conduct heat.
+    ///
+    /// Only awake chunks conduct heat, but they pass it on into sleeping neighbours and wake them
+    /// up if that makes a noticeable difference.
+    fn conduct_heat(&mut self) {
+        let awake: Vec<_> = self
+            .chunks
+            .iter()
+            .filter(|(_, _, chunk)| chunk.is_awake())
+            .map(|(column, row, _)| self.chunks.bounds(column, row))
+            .collect();
+        for bounds in awake {
+            for y in bounds.min_y..=bounds.max_y {
+                for x in bounds.min_x..=bounds.max_x {
+                    self.conduct_heat_at((x, y).into());
+                }
+            }
+        }
+    }
+
+    fn conduct_heat_at(&mut self, pos: Vec2) {
+        let Some(cell) = self.grid.get(&pos) else {
+            return;
+        };
+        let conductivity = self.materials.get(cell.material).conductivity;
+        let initial = cell.temperature;
+        let mut temperature = initial;
+
+        for direction in [
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+            Direction::Up,
+        ] {
+            let other_pos = self.grid.wrap(&(&pos + Vec2::from(direction)));
+            // pairs within awake chunks are handled from their upper left cell
+            let backwards = matches!(direction, Direction::Left | Direction::Up);
+            if backwards && self.chunks.is_awake(&other_pos) {
+                continue;
+            }
+            if let Some(other) = self.grid.get_mut(&other_pos) {
+                let other_conductivity = self.materials.get(other.material).conductivity;
+                // a quarter at most, so a cell never gives away more than it has
+                let flow =
+                    (other.temperature - temperature) * conductivity.min(other_conductivity) * 0.25;
+                other.temperature -= flow;
+                temperature += flow;
+                if flow.abs() > HEAT_EPSILON {
+                    self.chunks.wake(&other_pos);
+                }
+            }
+        }
+        let air = [
+            Direction::Up,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+        ]
+        .into_iter()
+        .map(|direction| self.grid.wrap(&(&pos + Vec2::from(direction))))
+        .filter(|other| self.grid.contains(other) && self.grid.get(other).is_none())
+        .count();
+        temperature +=
+            (AMBIENT_TEMPERATURE - temperature) * AIR_CONDUCTIVITY * conductivity * air as f32;
+
+        if let Some(cell) = self.grid.get_mut(&pos) {
+            cell.temperature = temperature;
+        }
+        if (temperature - initial).abs() > HEAT_EPSILON {
+            self.chunks.wake(&pos);
+        }
+    }
+
+    /// The colour of the particle at `pos`, `None` for empty cells and positions outside of the
+    /// world.
+    pub fn color_at(&self, pos: &Vec2) -> Option<Color> {
+        self.grid.get(pos).map(|cell| self.cell_color(cell))
+    }
+
     pub fn iter_cells(&self) -> impl Iterator<Item = (Position, Color)> + '_ {
-        let iter = self.src_buffer.iter().map(|(id, cell)| {
-            let pos: Vec2 = (*id).into();
-            let color = match *cell {
-                Cell::Sand => Color::Yellow,
-                Cell::Wood => Color::Rgb(25, 120, 25),
-                Cell::Fire => Color::Red,
-                Cell::Border => Color::Cyan,
-                Cell::Water => Color::Blue,
-            };
-            let res: Result<(u16, u16), eyre::Report> = pos.try_into();
-            if let Ok(pos) = res {
-                return Some((Position::from(pos), color));
+        self.grid.iter().map(|(pos, cell)| {
+            (
+                Position::new(pos.x as u16, pos.y as u16),
+                self.cell_color(cell),
+            )
+        })
+    }
+
+    /// A picture of the world with one pixel per cell in the colours of [`Simulation::iter_cells`],
+    /// on a black background.
+    pub fn snapshot(&self) -> Image {
+        let mut image = Image::new(self.width() as u32, self.height() as u32, (0, 0, 0));
+        for (pos, color) in self.iter_cells() {
+            if let Color::Rgb(r, g, b) = color {
+                image.set(pos.x as u32, pos.y as u32, (r, g, b));
             }
-            None
-        });
-        iter.flatten()
+        }
+        image
+    }
+
+    /// The colour of the material of `cell` in the shade of this particular particle, dimmed as it
+    /// reaches the end of its lifetime.
+    fn cell_color(&self, cell: &Cell) -> Color {
+        if self.render_mode == RenderMode::Temperature {
+            return heat_color(cell.temperature);
+        }
+        let definition = self.materials.get(cell.material);
+        let brightness = match (cell.lifetime, definition.lifetime) {
+            (Some(left), Some((_, max))) => 0.5 + 0.5 * (left as f32 / max as f32).min(1.0),
+            _ => 1.0,
+        };
+        let dim = |channel: u8| (channel as f32 * brightness) as u8;
+        let (r, g, b) = definition.color;
+        shade((dim(r), dim(g), dim(b)), cell.color_seed)
     }
+
+    /// Remembers the area the simulation is shown in and adapts the world to it according to the
+    /// [`ResizePolicy`]. The first window always sets the size of a world that has none yet.
     pub fn update_window_size(&mut self, window: Window) {
+        let (width, height) = (window.width, window.height);
+        let extent = (self.grid.width(), self.grid.height());
+        match self.resize_policy {
+            ResizePolicy::Crop => self.grid.resize(width, height),
+            ResizePolicy::Scroll if extent.0 == 0 || extent.1 == 0 => {
+                self.grid.resize(width, height)
+            }
+            ResizePolicy::Scroll => {}
+            ResizePolicy::Rescale => self.grid.rescale(width, height),
+        }
+        if extent != (self.grid.width(), self.grid.height()) {
+            self.chunks = ChunkMap::new(self.grid.width(), self.grid.height());
+        }
         self.window = Some(window)
     }
+
+    /// Fixes the extent of the world, independent of the window it is shown in, and switches to
+    /// [`ResizePolicy::Scroll`]. Particles beyond the new edges are discarded, extents beyond
+    /// [`MAX_EXTENT`] are cut down to it.
+    pub fn set_world_size(&mut self, width: u16, height: u16) {
+        let (width, height) = (width.min(MAX_EXTENT), height.min(MAX_EXTENT));
+        self.resize_policy = ResizePolicy::Scroll;
+        if (width, height) != (self.grid.width(), self.grid.height()) {
+            self.grid.resize(width, height);
+            self.chunks = ChunkMap::new(width, height);
+        }
+    }
+
+    /// Writes the whole world to `writer` in the format described in [`crate::save`].
+    pub fn save(&self, writer: &mut impl Write) -> Result<()> {
+        save::write(&self.grid, &self.materials, writer)
+    }
+
+    /// Replaces the world with one read from `reader` and fixes its extent like
+    /// [`Simulation::set_world_size`]. The world is left alone if the file can't be read.
+    pub fn load(&mut self, reader: &mut impl Read) -> Result<()> {
+        let grid = save::read(reader, &self.materials, &mut self.rng)?;
+        self.chunks = ChunkMap::new(grid.width(), grid.height());
+        self.grid = grid;
+        self.clock = 0;
+        self.resize_policy = ResizePolicy::Scroll;
+        Ok(())
+    }
+
+    /// Saves the world to the file at `path`, see [`Simulation::save`].
+    pub fn save_file(&self, path: &Path) -> Result<()> {
+        let file =
+            File::create(path).wrap_err_with(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        self.save(&mut writer)
+            .and_then(|()| Ok(writer.flush()?))
+            .wrap_err_with(|| format!("failed to save {}", path.display()))
+    }
+
+    /// Loads the world from the file at `path`, see [`Simulation::load`].
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let file =
+            File::open(path).wrap_err_with(|| format!("failed to open {}", path.display()))?;
+        self.load(&mut BufReader::new(file))
+            .wrap_err_with(|| format!("failed to load {}", path.display()))
+    }
+
+    /// The particles of the world as a hand-editable [`Level`].
+    pub fn level(&self) -> eyre::Result<Level> {
+        Level::from_grid(&self.grid, &self.materials)
+    }
+
+    pub fn resize_policy(&self) -> ResizePolicy {
+        self.resize_policy
+    }
+
+    /// Changes how the world adapts to future changes of the window size.
+    pub fn set_resize_policy(&mut self, resize_policy: ResizePolicy) {
+        self.resize_policy = resize_policy;
+    }
+
+    /// Extent of the world in cells.
+    pub fn width(&self) -> u16 {
+        self.grid.width()
+    }
+
+    pub fn height(&self) -> u16 {
+        self.grid.height()
+    }
+
+    /// The area of the terminal the simulation is shown in.
+    pub fn window(&self) -> Option<&Window> {
+        self.window.as_ref()
+    }
+
+    /// The chunks of the grid with the work done on each of them in the last tick.
+    pub fn chunks(&self) -> &ChunkMap {
+        &self.chunks
+    }
+
+    /// How much work the chunks saved in the last tick.
+    pub fn chunk_stats(&self) -> ChunkStats {
+        self.chunks.stats()
+    }
+
+    /// Number of particles currently in the simulation.
+    pub fn particle_count(&self) -> usize {
+        self.grid.count()
+    }
+
+    /// Number of particles of each material that is present, most common first.
+    pub fn material_counts(&self) -> Vec<(MaterialId, usize)> {
+        // ids index the registry, which starts with the border that iter() leaves out
+        let mut counts: Vec<(MaterialId, usize)> = std::iter::once(MaterialId::BORDER)
+            .chain(self.materials.iter().map(|(id, _)| id))
+            .map(|id| (id, 0))
+            .collect();
+        for (_, cell) in self.grid.iter() {
+            counts[cell.material.index()].1 += 1;
+        }
+        counts.retain(|(_, count)| *count > 0);
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts
+    }
 }
 
 impl MaterialCanvas for Simulation {
-    fn set_pixel(&mut self, pos: &Vec2, cell: Cell) -> eyre::Result<()> {
-        self.flip(pos, cell)?;
+    fn set_pixel(&mut self, pos: &Vec2, material: MaterialId) -> eyre::Result<()> {
+        self.flip(pos, material)?;
         Ok(())
     }
 
-    fn set_pixels(&mut self, points: &[Vec2], cell: Cell) -> eyre::Result<()> {
+    fn set_pixels(&mut self, points: &[Vec2], material: MaterialId) -> eyre::Result<()> {
         for pos in points {
-            self.set_pixel(pos, cell.clone())?;
+            self.set_pixel(pos, material)?;
         }
         Ok(())
     }
 
     fn remove_pixel(&mut self, pos: &Vec2) -> eyre::Result<()> {
-        let idx = pos.try_into()?;
-        self.src_buffer.remove(&idx);
+        self.grid.remove(pos);
+        self.wake(pos);
         Ok(())
     }
 }
 
+/// Positions of a single-row `rect`, in the scan direction of the current tick.
+fn scan(rect: &DirtyRect, left_to_right: bool) -> impl Iterator<Item = Vec2> + use<> {
+    let (y, min_x, max_x) = (rect.min_y, rect.min_x, rect.max_x);
+    (0..=max_x - min_x).map(move |i| {
+        let x = if left_to_right { min_x + i } else { max_x - i };
+        Vec2 { x, y }
+    })
+}
+
+/// Brightens or darkens `rgb` by a small amount derived from `seed`.
+fn shade((r, g, b): (u8, u8, u8), seed: u8) -> Color {
+    let offset = (seed % 32) as i16 - 16;
+    let channel = |value: u8| (value as i16 + offset).clamp(0, 255) as u8;
+    Color::Rgb(channel(r), channel(g), channel(b))
+}
+
+/// Maps a temperature onto a heat map running from blue over green and red to white.
+fn heat_color(temperature: f32) -> Color {
+    const STOPS: [(f32, (u8, u8, u8)); 6] = [
+        (-50.0, (0, 0, 255)),
+        (0.0, (0, 200, 255)),
+        (AMBIENT_TEMPERATURE, (0, 140, 0)),
+        (100.0, (255, 230, 0)),
+        (600.0, (255, 0, 0)),
+        (1500.0, (255, 255, 255)),
+    ];
+    let (mut lower, mut upper) = (STOPS[0], STOPS[STOPS.len() - 1]);
+    for window in STOPS.windows(2) {
+        if temperature >= window[0].0 && temperature <= window[1].0 {
+            (lower, upper) = (window[0], window[1]);
+        }
+    }
+    let t = ((temperature - lower.0) / (upper.0 - lower.0)).clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+    Color::Rgb(
+        mix(lower.1.0, upper.1.0),
+        mix(lower.1.1, upper.1.1),
+        mix(lower.1.2, upper.1.2),
+    )
+}
+
 pub trait MaterialCanvas {
-    fn set_pixel(&mut self, pos: &Vec2, cell: Cell) -> eyre::Result<()>;
-    fn set_pixels(&mut self, points: &[Vec2], cell: Cell) -> eyre::Result<()>;
+    fn set_pixel(&mut self, pos: &Vec2, material: MaterialId) -> eyre::Result<()>;
+    fn set_pixels(&mut self, points: &[Vec2], material: MaterialId) -> eyre::Result<()>;
     fn remove_pixel(&mut self, pos: &Vec2) -> eyre::Result<()>;
 }
 
@@ -233,192 +783,1564 @@ mod tests {
     #[cfg(test)]
     mod tests_finc_cell_h3_w3_empty_map {
         use super::*;
-        const HEIGHT: i16 = 3;
-        const WIDTH: i16 = 3;
+        const HEIGHT: u16 = 3;
+        const WIDTH: u16 = 3;
 
         #[test]
         fn test_cell_m1_m1_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((-1i16, -1).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((-1i16, -1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_0_m1_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((0i16, -1).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((0i16, -1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_1_m1_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((1i16, -1).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((1i16, -1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_2_m1_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((2i16, -1).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((2i16, -1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
 
         #[test]
         fn test_cell_3_m1_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((3i16, -1).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((3i16, -1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_m1_0_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((-1i16, 0).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((-1i16, 0).into(), &grid);
             let cell = cell_opt.unwrap();
-            assert!(matches!(cell, Cell::Border));
+            assert!(cell.is_border());
         }
         #[test]
         fn test_cell_0_0_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((0i16, 0).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((0i16, 0).into(), &grid);
             assert!(cell_opt.is_none());
         }
         #[test]
         fn test_cell_1_0_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((1i16, 0).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((1i16, 0).into(), &grid);
             assert!(cell_opt.is_none());
         }
         #[test]
         fn test_cell_2_0_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((2i16, 0).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((2i16, 0).into(), &grid);
             assert!(cell_opt.is_none());
         }
 
         #[test]
         fn test_cell_3_0_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((3i16, 0).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((3i16, 0).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
 
         #[test]
         fn test_cell_m1_1_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((-1i16, 1).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((-1i16, 1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_0_1_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((0i16, 1).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((0i16, 1).into(), &grid);
             assert!(cell_opt.is_none());
         }
         #[test]
         fn test_cell_1_1_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((1i16, 1).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((1i16, 1).into(), &grid);
             assert!(cell_opt.is_none());
         }
         #[test]
         fn test_cell_2_1_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((2i16, 1).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((2i16, 1).into(), &grid);
             assert!(cell_opt.is_none());
         }
 
         #[test]
         fn test_cell_3_1_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((3i16, 1).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((3i16, 1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
 
         #[test]
         fn test_cell_m1_2_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((-1i16, 2).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((-1i16, 2).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_0_2_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((0i16, 2).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((0i16, 2).into(), &grid);
             assert!(cell_opt.is_none());
         }
         #[test]
         fn test_cell_1_2_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((1i16, 2).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((1i16, 2).into(), &grid);
             assert!(cell_opt.is_none());
         }
         #[test]
         fn test_cell_2_2_is_empty() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((2i16, 2).into(), WIDTH, HEIGHT, &hash_map);
+            let cell_opt = Simulation::find_cell((2i16, 2).into(), &grid);
             assert!(cell_opt.is_none());
         }
 
         #[test]
         fn test_cell_3_2_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((3i16, 2).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((3i16, 2).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
 
         #[test]
         fn test_cell_m1_3_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((-1i16, 3).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((-1i16, 3).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_0_3_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((0i16, 3).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((0i16, 3).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_1_3_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((1i16, 3).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((1i16, 3).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
         #[test]
         fn test_cell_2_3_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
 
-            let cell_opt = Simulation::find_cell((2i16, 3).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            let cell_opt = Simulation::find_cell((2i16, 3).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
         }
 
         #[test]
         fn test_cell_3_3_is_border() {
-            let hash_map = HashMap::new();
+            let grid = Grid::new(WIDTH, HEIGHT);
+
+            let cell_opt = Simulation::find_cell((3i16, 3).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_find_cell_h3_w3_void_map {
+        use super::*;
+        const HEIGHT: u16 = 3;
+        const WIDTH: u16 = 3;
+
+        fn grid() -> Grid {
+            let mut grid = Grid::new(WIDTH, HEIGHT);
+            grid.set_boundaries(Boundaries::all(Boundary::Void));
+            grid
+        }
+
+        #[test]
+        fn test_cell_m1_m1_is_empty() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((-1i16, -1).into(), &grid);
+            assert!(cell_opt.is_none());
+        }
+        #[test]
+        fn test_cell_1_m1_is_empty() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((1i16, -1).into(), &grid);
+            assert!(cell_opt.is_none());
+        }
+        #[test]
+        fn test_cell_3_1_is_empty() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((3i16, 1).into(), &grid);
+            assert!(cell_opt.is_none());
+        }
+        #[test]
+        fn test_cell_1_3_is_empty() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((1i16, 3).into(), &grid);
+            assert!(cell_opt.is_none());
+        }
+        #[test]
+        fn test_cell_1_1_is_empty() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((1i16, 1).into(), &grid);
+            assert!(cell_opt.is_none());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_find_cell_h3_w3_wrapped_map {
+        use super::*;
+        const HEIGHT: u16 = 3;
+        const WIDTH: u16 = 3;
+
+        /// A wrapping grid with sand in its upper left and water in its lower right corner.
+        fn grid() -> Grid {
+            let materials = MaterialRegistry::default();
+            let mut grid = Grid::new(WIDTH, HEIGHT);
+            grid.set_boundaries(Boundaries::all(Boundary::Wrap));
+            for (pos, name) in [((0i16, 0i16), "sand"), ((2, 2), "water")] {
+                let cell = Cell::new(materials.find(name).unwrap(), &materials, &mut rand::rng());
+                grid.insert(&pos.into(), cell).unwrap();
+            }
+            grid
+        }
+
+        fn name(cell: Option<&Cell>) -> &str {
+            let materials = MaterialRegistry::default();
+            match cell {
+                Some(cell) if cell.is_border() => "border",
+                Some(cell) => {
+                    if Some(cell.material) == materials.find("sand") {
+                        "sand"
+                    } else {
+                        "water"
+                    }
+                }
+                None => "empty",
+            }
+        }
+
+        #[test]
+        fn test_cell_m1_m1_is_water() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((-1i16, -1).into(), &grid);
+            assert_eq!(name(cell_opt), "water");
+        }
+        #[test]
+        fn test_cell_3_3_is_sand() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((3i16, 3).into(), &grid);
+            assert_eq!(name(cell_opt), "sand");
+        }
+        #[test]
+        fn test_cell_3_0_is_sand() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((3i16, 0).into(), &grid);
+            assert_eq!(name(cell_opt), "sand");
+        }
+        #[test]
+        fn test_cell_2_m1_is_water() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((2i16, -1).into(), &grid);
+            assert_eq!(name(cell_opt), "water");
+        }
+        #[test]
+        fn test_cell_1_m1_is_empty() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((1i16, -1).into(), &grid);
+            assert_eq!(name(cell_opt), "empty");
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_find_cell_h3_w3_mixed_map {
+        use super::*;
+        const HEIGHT: u16 = 3;
+        const WIDTH: u16 = 3;
+
+        /// A drain: walls on the sides and the top, void below.
+        fn grid() -> Grid {
+            let mut grid = Grid::new(WIDTH, HEIGHT);
+            grid.set_boundaries(Boundaries {
+                bottom: Boundary::Void,
+                ..Boundaries::default()
+            });
+            grid
+        }
+
+        #[test]
+        fn test_cell_1_3_is_empty() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((1i16, 3).into(), &grid);
+            assert!(cell_opt.is_none());
+        }
+        #[test]
+        fn test_cell_1_m1_is_border() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((1i16, -1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
+        }
+        #[test]
+        fn test_cell_3_1_is_border() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((3i16, 1).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
+        }
+        #[test]
+        fn test_cell_m1_3_is_border() {
+            let grid = grid();
+
+            let cell_opt = Simulation::find_cell((-1i16, 3).into(), &grid);
+            assert!(cell_opt.unwrap().is_border());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_camera {
+        use crossterm::event::{KeyModifiers, MouseButton, MouseEventKind};
+
+        use super::*;
+
+        /// A 100x50 world shown in a 20x10 window at column 5 and row 2 of the terminal.
+        fn simulation() -> Simulation {
+            let mut simulation = Simulation::default();
+            simulation.set_world_size(100, 50);
+            simulation.update_window_size(Window {
+                height: 10,
+                width: 20,
+                x: 5,
+                y: 2,
+            });
+            simulation
+        }
+
+        #[test]
+        fn test_world_size_is_independent_of_window() {
+            let simulation = simulation();
+
+            assert_eq!((simulation.width(), simulation.height()), (100, 50));
+            assert!(Simulation::find_cell((99i16, 49).into(), &simulation.grid).is_none());
+            assert!(Simulation::find_cell((100i16, 49).into(), &simulation.grid).is_some());
+        }
+
+        #[test]
+        fn test_painting_goes_through_camera() {
+            let mut simulation = simulation();
+            let event = MouseEvent {
+                kind: MouseEventKind::Drag(MouseButton::Left),
+                column: 6,
+                row: 3,
+                modifiers: KeyModifiers::NONE,
+            };
+
+            simulation
+                .handle_mouse_event(&event, &(40i16, 30).into())
+                .unwrap();
+
+            let positions: Vec<(i16, i16)> =
+                simulation.grid.iter().map(|(pos, _)| pos.into()).collect();
+            assert_eq!(positions, vec![(41, 31)]);
+        }
+
+        #[test]
+        fn test_camera_stays_within_world() {
+            let simulation = simulation();
+
+            assert_eq!(
+                simulation.clamp_camera(&(-3i16, 45).into()),
+                (0i16, 40).into()
+            );
+            assert_eq!(
+                simulation.clamp_camera(&(200i16, 10).into()),
+                (80i16, 10).into()
+            );
+        }
+
+        #[test]
+        fn test_world_smaller_than_window_stays_in_corner() {
+            let mut simulation = simulation();
+            simulation.set_world_size(10, 5);
+
+            assert_eq!(simulation.clamp_camera(&(3i16, 3).into()), Vec2::default());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_brush {
+        use crossterm::event::KeyModifiers;
+
+        use super::*;
+        use crate::brush::BrushShape;
+
+        fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+            MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            }
+        }
+
+        fn simulation() -> Simulation {
+            let mut simulation = Simulation::default();
+            simulation.update_window_size(Window {
+                height: 20,
+                width: 20,
+                x: 0,
+                y: 0,
+            });
+            simulation
+        }
+
+        #[test]
+        fn test_fast_drag_paints_continuous_line() {
+            let mut simulation = simulation();
+            let camera = Vec2::default();
+            let left = MouseButton::Left;
+            for event in [
+                mouse(MouseEventKind::Down(left), 2, 5),
+                mouse(MouseEventKind::Drag(left), 9, 5),
+                mouse(MouseEventKind::Up(left), 9, 5),
+                mouse(MouseEventKind::Drag(left), 15, 5),
+            ] {
+                simulation.handle_mouse_event(&event, &camera).unwrap();
+            }
+
+            let columns: Vec<i16> = simulation.grid.iter().map(|(pos, _)| pos.x).collect();
+            // the drag after lifting the button starts a new stroke
+            assert_eq!(columns, vec![2, 3, 4, 5, 6, 7, 8, 9, 15]);
+        }
+
+        #[test]
+        fn test_left_button_paints_selected_material() {
+            let mut simulation = simulation();
+            let wood = simulation.materials.find("wood").unwrap();
+            // wood is the third material of the palette
+            simulation
+                .handle_keyboard_event(&KeyEvent::from(KeyCode::Char('3')))
+                .unwrap();
+            assert_eq!(simulation.selected(), Some(wood));
+
+            let event = mouse(MouseEventKind::Down(MouseButton::Left), 4, 4);
+            simulation
+                .handle_mouse_event(&event, &Vec2::default())
+                .unwrap();
+
+            assert_eq!(
+                simulation
+                    .grid
+                    .get(&(4i16, 4).into())
+                    .map(|cell| cell.material),
+                Some(wood)
+            );
+        }
+
+        #[test]
+        fn test_zero_key_selects_tenth_material() {
+            let mut simulation = simulation();
+            simulation
+                .handle_keyboard_event(&KeyEvent::from(KeyCode::Char('0')))
+                .unwrap();
+
+            let tenth = simulation.materials.iter().nth(9).map(|(id, _)| id);
+            assert_eq!(simulation.selected(), tenth);
+        }
+
+        #[test]
+        fn test_scroll_wheel_resizes_brush() {
+            let mut simulation = simulation();
+            for _ in 0..3 {
+                simulation
+                    .handle_mouse_event(&mouse(MouseEventKind::ScrollUp, 0, 0), &Vec2::default())
+                    .unwrap();
+            }
+            simulation
+                .handle_keyboard_event(&KeyEvent::from(KeyCode::Char('[')))
+                .unwrap();
+
+            assert_eq!(simulation.brush().radius(), 2);
+        }
+
+        #[test]
+        fn test_eraser_removes_particles_under_brush() {
+            let mut simulation = simulation();
+            let sand = simulation.materials.find("sand").unwrap();
+            for y in 0..20i16 {
+                for x in 0..20i16 {
+                    simulation.set_pixel(&(x, y).into(), sand).unwrap();
+                }
+            }
+            simulation.brush_mut().set_radius(1);
+            simulation.brush_mut().set_shape(BrushShape::Square);
+            simulation
+                .handle_keyboard_event(&KeyEvent::from(KeyCode::Char('e')))
+                .unwrap();
+
+            let event = mouse(MouseEventKind::Down(MouseButton::Left), 10, 10);
+            simulation
+                .handle_mouse_event(&event, &Vec2::default())
+                .unwrap();
+
+            assert_eq!(simulation.particle_count(), 400 - 9);
+            assert!(simulation.grid.get(&(9i16, 9).into()).is_none());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_resize_policy {
+        use super::*;
+
+        fn window(width: u16, height: u16) -> Window {
+            Window {
+                height,
+                width,
+                x: 0,
+                y: 0,
+            }
+        }
+
+        /// A 10x10 world with a grain of sand in the upper left and one in the lower right quarter,
+        /// shrunk to 5x5.
+        fn shrink(policy: ResizePolicy) -> Simulation {
+            let mut simulation = Simulation::default();
+            simulation.set_resize_policy(policy);
+            simulation.update_window_size(window(10, 10));
+            let sand = simulation.materials.find("sand").unwrap();
+            simulation.set_pixel(&(2i16, 2).into(), sand).unwrap();
+            simulation.set_pixel(&(8i16, 8).into(), sand).unwrap();
+
+            simulation.update_window_size(window(5, 5));
+            simulation
+        }
+
+        fn positions(simulation: &Simulation) -> Vec<(i16, i16)> {
+            simulation.grid.iter().map(|(pos, _)| pos.into()).collect()
+        }
+
+        #[test]
+        fn test_crop_discards_particles_beyond_new_edges() {
+            let mut simulation = shrink(ResizePolicy::Crop);
+
+            assert_eq!((simulation.width(), simulation.height()), (5, 5));
+            assert_eq!(positions(&simulation), vec![(2, 2)]);
+
+            for _ in 0..10 {
+                simulation.handle_ticks().unwrap();
+            }
+            assert_eq!(positions(&simulation), vec![(2, 4)]);
+        }
+
+        #[test]
+        fn test_scroll_keeps_world() {
+            let simulation = shrink(ResizePolicy::Scroll);
+
+            assert_eq!((simulation.width(), simulation.height()), (10, 10));
+            assert_eq!(positions(&simulation), vec![(2, 2), (8, 8)]);
+            // the camera reaches the part that no longer fits into the window
+            assert_eq!(simulation.clamp_camera(&(8i16, 8).into()), (5i16, 5).into());
+        }
+
+        #[test]
+        fn test_rescale_squeezes_world_into_window() {
+            let simulation = shrink(ResizePolicy::Rescale);
+
+            assert_eq!((simulation.width(), simulation.height()), (5, 5));
+            assert_eq!(positions(&simulation), vec![(1, 1), (4, 4)]);
+        }
+
+        #[test]
+        fn test_set_world_size_switches_to_scroll() {
+            let mut simulation = Simulation::default();
+            simulation.set_world_size(30, 20);
+            simulation.update_window_size(window(5, 5));
+
+            assert_eq!(simulation.resize_policy(), ResizePolicy::Scroll);
+            assert_eq!((simulation.width(), simulation.height()), (30, 20));
+        }
+
+        #[test]
+        fn test_set_world_size_is_limited() {
+            let mut simulation = Simulation::default();
+            simulation.set_world_size(40000, 10);
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!((simulation.width(), simulation.height()), (MAX_EXTENT, 10));
+        }
+
+        #[test]
+        fn test_far_edge_of_largest_world() {
+            let mut simulation = Simulation::default();
+            simulation.update_window_size(window(20, 3));
+            simulation.set_world_size(MAX_EXTENT, 3);
+            simulation.set_boundaries(Boundaries {
+                right: Boundary::Void,
+                ..Boundaries::default()
+            });
+            simulation.brush_mut().set_radius(crate::brush::MAX_RADIUS);
+            let camera = simulation.clamp_camera(&(i16::MAX, 0).into());
+            let event = MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Right),
+                column: 19,
+                row: 1,
+                modifiers: crossterm::event::KeyModifiers::NONE,
+            };
+
+            simulation.handle_mouse_event(&event, &camera).unwrap();
+            assert!(simulation.particle_count() > 0);
+            for _ in 0..20 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            let last = MAX_EXTENT as i16 - 1;
+            assert!(simulation.grid.iter().all(|(pos, _)| pos.x <= last));
+        }
+
+        #[test]
+        fn test_load_keeps_saved_world_size() {
+            let saved = shrink(ResizePolicy::Scroll);
+            let mut bytes = vec![];
+            saved.save(&mut bytes).unwrap();
+
+            let mut simulation = Simulation::default();
+            simulation.update_window_size(window(40, 40));
+            simulation.load(&mut bytes.as_slice()).unwrap();
+            simulation.update_window_size(window(5, 5));
+
+            assert_eq!(simulation.resize_policy(), ResizePolicy::Scroll);
+            assert_eq!((simulation.width(), simulation.height()), (10, 10));
+            assert_eq!(positions(&simulation), vec![(2, 2), (8, 8)]);
+
+            // a broken file leaves the world alone
+            assert!(simulation.load(&mut &bytes[..20]).is_err());
+            assert_eq!(positions(&simulation), vec![(2, 2), (8, 8)]);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_snapshot {
+        use super::*;
+
+        #[test]
+        fn test_snapshot_uses_cell_colors() {
+            let mut simulation = Simulation::default();
+            simulation.set_world_size(4, 3);
+            let sand = simulation.materials.find("sand").unwrap();
+            simulation.set_pixel(&(1i16, 2).into(), sand).unwrap();
+
+            let image = simulation.snapshot();
+
+            assert_eq!((image.width(), image.height()), (4, 3));
+            let (_, Color::Rgb(r, g, b)) = simulation.iter_cells().next().unwrap() else {
+                panic!("cells are drawn in RGB");
+            };
+            assert_eq!(image.get(1, 2), Some((r, g, b)));
+            assert_eq!(image.get(0, 0), Some((0, 0, 0)));
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_material_counts {
+        use super::*;
+
+        fn simulation() -> Simulation {
+            let mut simulation = Simulation::default();
+            simulation.set_world_size(20, 20);
+            simulation
+        }
+
+        #[test]
+        fn test_material_counts_most_common_first() {
+            let mut simulation = simulation();
+            let sand = simulation.materials.find("sand").unwrap();
+            let water = simulation.materials.find("water").unwrap();
+            simulation.set_pixel(&(0i16, 0).into(), sand).unwrap();
+            for x in 0..3i16 {
+                simulation.set_pixel(&(x, 5).into(), water).unwrap();
+            }
+
+            assert_eq!(simulation.material_counts(), vec![(water, 3), (sand, 1)]);
+        }
+
+        #[test]
+        fn test_material_counts_include_stray_border() {
+            let mut simulation = simulation();
+            let sand = simulation.materials.find("sand").unwrap();
+            simulation.set_pixel(&(0i16, 0).into(), sand).unwrap();
+            // a reaction or phase change could leave one behind
+            simulation
+                .grid
+                .insert(&(1i16, 0).into(), crate::particle::BORDER.clone())
+                .unwrap();
+
+            assert_eq!(
+                simulation.material_counts(),
+                vec![(MaterialId::BORDER, 1), (sand, 1)]
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod tests_handle_ticks {
+        use std::sync::Arc;
+
+        use super::*;
+        use crate::{behaviour::MaterialBehaviour, grid::Neighbourhood, particle::Action};
+
+        fn id(name: &str) -> MaterialId {
+            MaterialRegistry::default().find(name).unwrap()
+        }
+
+        fn simulation(width: u16, height: u16) -> Simulation {
+            let mut simulation = Simulation::default();
+            simulation.update_window_size(Window {
+                height,
+                width,
+                x: 0,
+                y: 0,
+            });
+            simulation
+        }
+
+        fn assert_particles_are_conserved(materials: &[&str]) {
+            let mut simulation = simulation(30, 20);
+            // a checkerboard in the upper half keeps particles colliding while they settle
+            let points: Vec<Vec2> = (0..10i16)
+                .flat_map(|y| {
+                    (0..30i16)
+                        .filter(move |x| (x + y) % 2 == 0)
+                        .map(move |x| (x, y).into())
+                })
+                .collect();
+            for (pos, material) in points.iter().zip(materials.iter().cycle()) {
+                simulation.set_pixel(pos, id(material)).unwrap();
+            }
+
+            for tick in 0..5000 {
+                simulation.handle_ticks().unwrap();
+                assert_eq!(
+                    simulation.particle_count(),
+                    points.len(),
+                    "particles were lost or duplicated in tick {tick}"
+                );
+            }
+        }
+
+        #[test]
+        fn test_sand_is_conserved() {
+            assert_particles_are_conserved(&["sand"]);
+        }
+
+        #[test]
+        fn test_water_is_conserved() {
+            assert_particles_are_conserved(&["water"]);
+        }
+
+        #[test]
+        fn test_sand_sinking_through_water_is_conserved() {
+            assert_particles_are_conserved(&["sand", "water"]);
+        }
+
+        fn column(materials: &[&str]) -> Simulation {
+            let mut simulation = simulation(1, materials.len() as u16);
+            for (y, material) in materials.iter().enumerate() {
+                simulation
+                    .set_pixel(&(0i16, y as i16).into(), id(material))
+                    .unwrap();
+            }
+            simulation
+        }
+
+        fn column_materials(simulation: &Simulation) -> Vec<&str> {
+            simulation
+                .grid
+                .iter()
+                .map(|(_, cell)| simulation.materials.get(cell.material).name.as_str())
+                .collect()
+        }
+
+        #[test]
+        fn test_sand_sinks_through_water() {
+            let mut simulation = column(&["sand", "water", "water"]);
+
+            for _ in 0..5 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(
+                column_materials(&simulation),
+                vec!["water", "water", "sand"]
+            );
+        }
+
+        #[test]
+        fn test_water_rests_on_sand() {
+            let mut simulation = column(&["water", "sand", "sand"]);
+
+            for _ in 0..5 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(column_materials(&simulation), vec!["water", "sand", "sand"]);
+        }
+
+        #[test]
+        fn test_water_column_flattens() {
+            let mut simulation = simulation(12, 7);
+            // the shaft holding the column opens into a gap of one cell under a shelf, the water
+            // can only get out by spreading sideways beneath more water or stone
+            let shaft: Vec<Vec2> = (0..5i16)
+                .map(|y| (1i16, y).into())
+                .chain((1..12i16).map(|x| (x, 5i16).into()))
+                .collect();
+            simulation.set_pixels(&shaft, id("stone")).unwrap();
+            let column: Vec<Vec2> = (0..7i16).map(|y| (0i16, y).into()).collect();
+            simulation.set_pixels(&column, id("water")).unwrap();
+
+            for _ in 0..200 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            let water: Vec<Vec2> = simulation
+                .grid
+                .iter()
+                .filter(|(_, cell)| cell.material == id("water"))
+                .map(|(pos, _)| pos)
+                .collect();
+            assert!(water.iter().all(|pos| pos.y == 6), "{water:?}");
+        }
+
+        #[test]
+        fn test_particles_competing_for_a_cell_both_survive() {
+            let mut simulation = simulation(3, 2);
+            simulation
+                .set_pixels(&[(0i16, 1).into(), (2i16, 1).into()], id("wood"))
+                .unwrap();
+            simulation
+                .set_pixels(&[(0i16, 0).into(), (2i16, 0).into()], id("sand"))
+                .unwrap();
+
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!(simulation.particle_count(), 4);
+            assert_eq!(
+                simulation
+                    .grid
+                    .get(&(1i16, 1).into())
+                    .map(|cell| cell.material),
+                Some(id("sand"))
+            );
+        }
+
+        #[test]
+        fn test_fire_burns_for_its_lifetime() {
+            let mut simulation = simulation(1, 1);
+            let fire = Cell {
+                lifetime: Some(3),
+                ..Cell::new(id("fire"), &simulation.materials, &mut simulation.rng)
+            };
+            simulation.grid.insert(&(0i16, 0).into(), fire).unwrap();
+
+            simulation.handle_ticks().unwrap();
+            simulation.handle_ticks().unwrap();
+            assert_eq!(simulation.particle_count(), 1);
+            simulation.handle_ticks().unwrap();
+            assert_eq!(simulation.particle_count(), 0);
+        }
+
+        #[test]
+        fn test_falling_sand_accelerates() {
+            let mut simulation = simulation(1, 80);
+            simulation.set_pixel(&(0i16, 0).into(), id("sand")).unwrap();
+
+            let mut depths = vec![];
+            for _ in 0..16 {
+                simulation.handle_ticks().unwrap();
+                let (pos, _) = simulation.grid.iter().next().unwrap();
+                depths.push(pos.y);
+            }
+
+            let first_steps = depths[1] - depths[0];
+            let last_steps = depths[15] - depths[14];
+            assert!(
+                last_steps > first_steps,
+                "sand fell {first_steps} and later {last_steps} cells per tick"
+            );
+        }
+
+        #[test]
+        fn test_wood_catches_fire() {
+            let mut simulation = column(&["wood", "wood", "fire"]);
+
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!(column_materials(&simulation), vec!["wood", "fire", "fire"]);
+        }
+
+        #[test]
+        fn test_reactions_from_material_file() {
+            let materials = MaterialRegistry::parse(
+                r##"
+[[material]]
+name = "acid"
+color = "#80ff00"
+density = 1200.0
+state = "liquid"
+behaviour = "liquid"
+
+[[material]]
+name = "metal"
+color = "#a0a0a0"
+density = 7800.0
+state = "solid"
+behaviour = "static"
+reactions = [{ with = "acid", becomes = "acid" }]
+"##,
+            )
+            .unwrap();
+            let mut simulation = Simulation::new(materials);
+            simulation.update_window_size(Window {
+                height: 2,
+                width: 1,
+                x: 0,
+                y: 0,
+            });
+            let (acid, metal) = (
+                simulation.materials.find("acid").unwrap(),
+                simulation.materials.find("metal").unwrap(),
+            );
+            simulation.set_pixel(&(0i16, 0).into(), acid).unwrap();
+            simulation.set_pixel(&(0i16, 1).into(), metal).unwrap();
+
+            simulation.handle_ticks().unwrap();
+            simulation.handle_ticks().unwrap();
+
+            assert!(
+                simulation
+                    .grid
+                    .iter()
+                    .all(|(_, cell)| cell.material == acid)
+            );
+        }
+
+        #[test]
+        fn test_water_puts_out_fire() {
+            let mut simulation = column(&["fire", "water"]);
+
+            simulation.handle_ticks().unwrap();
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!(column_materials(&simulation), vec!["steam", "water"]);
+        }
+
+        #[test]
+        fn test_lava_and_water_turn_into_stone() {
+            let mut simulation = column(&["water", "lava"]);
+
+            simulation.handle_ticks().unwrap();
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!(column_materials(&simulation), vec!["stone"]);
+        }
+
+        #[test]
+        fn test_acid_dissolves_wood() {
+            let mut simulation = column(&["acid", "wood", "wood"]);
+
+            for _ in 0..200 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(simulation.particle_count(), 1);
+            assert_eq!(column_materials(&simulation), vec!["wood"]);
+        }
+
+        fn cell_at(simulation: &Simulation, pos: (i16, i16)) -> &Cell {
+            simulation.grid.get(&pos.into()).unwrap()
+        }
+
+        #[test]
+        fn test_heat_flows_from_hot_to_cold() {
+            let mut simulation = column(&["stone", "stone"]);
+            simulation
+                .grid
+                .get_mut(&(0i16, 0).into())
+                .unwrap()
+                .temperature = 500.0;
+
+            simulation.handle_ticks().unwrap();
+
+            let (hot, cold) = (cell_at(&simulation, (0, 0)), cell_at(&simulation, (0, 1)));
+            assert!(hot.temperature < 500.0, "{}", hot.temperature);
+            assert!(
+                cold.temperature > AMBIENT_TEMPERATURE,
+                "{}",
+                cold.temperature
+            );
+        }
+
+        #[test]
+        fn test_hot_water_boils() {
+            let mut simulation = simulation(1, 1);
+            let water = Cell {
+                temperature: 150.0,
+                ..Cell::new(id("water"), &simulation.materials, &mut simulation.rng)
+            };
+            simulation.grid.insert(&(0i16, 0).into(), water).unwrap();
+
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!(column_materials(&simulation), vec!["steam"]);
+        }
+
+        #[test]
+        fn test_ice_melts() {
+            let mut simulation = simulation(3, 3);
+            simulation.set_pixel(&(1i16, 1).into(), id("ice")).unwrap();
+
+            simulation.handle_ticks().unwrap();
+            assert_eq!(column_materials(&simulation), vec!["ice"]);
+            // the surrounding air warms the ice up
+            for _ in 0..100 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(column_materials(&simulation), vec!["water"]);
+        }
+
+        #[test]
+        fn test_lava_cools_into_stone() {
+            let mut simulation = column(&["lava", "ice", "ice"]);
+            simulation
+                .grid
+                .get_mut(&(0i16, 0).into())
+                .unwrap()
+                .temperature = 801.0;
+
+            for _ in 0..5 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(column_materials(&simulation)[0], "stone");
+        }
+
+        #[test]
+        fn test_steam_rises() {
+            let mut simulation = simulation(1, 3);
+            simulation
+                .set_pixel(&(0i16, 2).into(), id("steam"))
+                .unwrap();
+
+            simulation.handle_ticks().unwrap();
+            simulation.handle_ticks().unwrap();
+
+            let (pos, _) = simulation.grid.iter().next().unwrap();
+            assert_eq!(pos, (0i16, 0).into());
+        }
+
+        #[test]
+        fn test_temperature_render_mode_toggles() {
+            let mut simulation = simulation(1, 1);
+            assert_eq!(simulation.render_mode(), RenderMode::Materials);
+
+            simulation
+                .handle_keyboard_event(&KeyEvent::from(KeyCode::Char('t')))
+                .unwrap();
+            assert_eq!(simulation.render_mode(), RenderMode::Temperature);
+        }
+
+        #[test]
+        fn test_fire_emits_smoke() {
+            let mut simulation = simulation(1, 10);
+            let fire = Cell {
+                lifetime: None,
+                ..Cell::new(id("fire"), &simulation.materials, &mut simulation.rng)
+            };
+            simulation.grid.insert(&(0i16, 9).into(), fire).unwrap();
+
+            for _ in 0..40 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert!(column_materials(&simulation).contains(&"smoke"));
+        }
+
+        #[test]
+        fn test_smoke_rises_and_fades() {
+            let mut simulation = simulation(1, 10);
+            simulation
+                .set_pixel(&(0i16, 9).into(), id("smoke"))
+                .unwrap();
+
+            for _ in 0..10 {
+                simulation.handle_ticks().unwrap();
+            }
+            let (pos, _) = simulation.grid.iter().next().unwrap();
+            assert_eq!(pos, (0i16, 0).into());
+
+            for _ in 0..60 {
+                simulation.handle_ticks().unwrap();
+            }
+            assert_eq!(simulation.particle_count(), 0);
+        }
+
+        #[test]
+        fn test_smoke_rises_through_water() {
+            let mut simulation = column(&["water", "water", "smoke"]);
+
+            for _ in 0..3 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(column_materials(&simulation)[0], "smoke");
+        }
+
+        #[test]
+        fn test_water_pushes_smoke_aside() {
+            let mut simulation = simulation(2, 2);
+            simulation
+                .set_pixel(&(0i16, 1).into(), id("water"))
+                .unwrap();
+            simulation
+                .set_pixel(&(1i16, 1).into(), id("smoke"))
+                .unwrap();
+
+            let pos: Vec2 = (0i16, 1).into();
+            let neighbours =
+                Neighbourhood::new(&simulation.grid, &simulation.materials, pos.clone());
+            let action = simulation
+                .grid
+                .get(&pos)
+                .unwrap()
+                .update(&neighbours, &mut simulation.rng)
+                .unwrap();
+
+            assert!(matches!(action, Action::Swap(Direction::Right)));
+        }
+
+        #[test]
+        fn test_oil_floats_on_water() {
+            let mut simulation = column(&["water", "oil", "oil"]);
+
+            for _ in 0..5 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(column_materials(&simulation), vec!["oil", "oil", "water"]);
+        }
+
+        #[test]
+        fn test_moss_grows() {
+            let mut simulation = simulation(5, 5);
+            simulation.set_pixel(&(2i16, 4).into(), id("moss")).unwrap();
+
+            for _ in 0..500 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert!(simulation.particle_count() > 1);
+        }
+
+        #[derive(Debug)]
+        struct Drift;
+
+        impl MaterialBehaviour for Drift {
+            fn update(
+                &self,
+                cell: &Cell,
+                neighbours: &Neighbourhood,
+                _rng: &mut SimulationRng,
+            ) -> Result<Action> {
+                Ok(cell
+                    .try_move(neighbours, Direction::Right)
+                    .unwrap_or(Action::None))
+            }
+        }
+
+        #[test]
+        fn test_custom_behaviour() {
+            let mut materials = MaterialRegistry::default();
+            materials.set_behaviour(id("stone"), Arc::new(Drift));
+            let mut simulation = Simulation::new(materials);
+            simulation.update_window_size(Window {
+                height: 1,
+                width: 3,
+                x: 0,
+                y: 0,
+            });
+            simulation
+                .set_pixel(&(0i16, 0).into(), id("stone"))
+                .unwrap();
+
+            for _ in 0..3 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            let (pos, _) = simulation.grid.iter().next().unwrap();
+            assert_eq!(pos, (2i16, 0).into());
+        }
+
+        #[test]
+        fn test_water_disperses_several_cells_per_tick() {
+            let mut simulation = simulation(10, 2);
+            simulation
+                .set_pixel(&(0i16, 1).into(), id("water"))
+                .unwrap();
+
+            simulation.handle_ticks().unwrap();
+
+            let (pos, _) = simulation.grid.iter().next().unwrap();
+            assert_eq!(pos, (5i16, 1).into());
+        }
+
+        #[test]
+        fn test_dispersion_stops_at_obstacles() {
+            let mut simulation = simulation(10, 2);
+            simulation
+                .set_pixel(&(0i16, 1).into(), id("water"))
+                .unwrap();
+            simulation.set_pixel(&(3i16, 1).into(), id("wood")).unwrap();
+
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!(
+                simulation
+                    .grid
+                    .get(&(2i16, 1).into())
+                    .map(|cell| cell.material),
+                Some(id("water"))
+            );
+        }
+
+        #[test]
+        fn test_widest_dispersion_stays_within_reach() {
+            let mut materials = MaterialRegistry::default();
+            let liquid = crate::behaviour::Liquid {
+                dispersion: crate::chunk::MAX_REACH as u16,
+                viscosity: 0.0,
+            };
+            materials.set_behaviour(id("water"), Arc::new(liquid));
+            let mut simulation = Simulation::new(materials);
+            simulation.update_window_size(Window {
+                height: 1,
+                width: 10,
+                x: 0,
+                y: 0,
+            });
+            // either particle slides all the way up to the other one
+            simulation
+                .set_pixels(&[(0i16, 0).into(), (9i16, 0).into()], id("water"))
+                .unwrap();
+
+            simulation.handle_ticks().unwrap();
+
+            assert_eq!(simulation.particle_count(), 2);
+        }
+
+        #[test]
+        fn test_honey_flows_slower_than_water() {
+            let mut simulation = simulation(2, 40);
+            simulation
+                .set_pixel(&(0i16, 0).into(), id("water"))
+                .unwrap();
+            simulation
+                .set_pixel(&(1i16, 0).into(), id("honey"))
+                .unwrap();
+
+            for _ in 0..10 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            let depth = |material| {
+                simulation
+                    .grid
+                    .iter()
+                    .find(|(_, cell)| cell.material == id(material))
+                    .map(|(pos, _)| pos.y)
+                    .unwrap()
+            };
+            assert!(depth("honey") < depth("water"));
+        }
+
+        /// Runs a busy scene of falling, flowing, burning and reacting particles.
+        fn run_seeded(seed: u64) -> Vec<String> {
+            let mut simulation = Simulation::with_seed(MaterialRegistry::default(), seed);
+            simulation.update_window_size(Window {
+                height: 20,
+                width: 30,
+                x: 0,
+                y: 0,
+            });
+            let materials = ["sand", "water", "wood", "fire", "oil", "salt", "moss"];
+            for y in 0..10i16 {
+                for x in 0..30i16 {
+                    let material = materials[(x + y) as usize % materials.len()];
+                    simulation.set_pixel(&(x, y).into(), id(material)).unwrap();
+                }
+            }
+
+            for _ in 0..200 {
+                simulation.handle_ticks().unwrap();
+            }
+            simulation
+                .grid
+                .iter()
+                .map(|(pos, cell)| format!("{pos:?} {cell:?}"))
+                .collect()
+        }
+
+        #[test]
+        fn test_same_seed_gives_identical_grids() {
+            assert_eq!(run_seeded(42), run_seeded(42));
+        }
+
+        #[test]
+        fn test_different_seeds_give_different_grids() {
+            assert_ne!(run_seeded(1), run_seeded(2));
+        }
+
+        #[test]
+        fn test_settled_particles_let_chunks_sleep() {
+            let mut simulation = simulation(40, 40);
+            for y in 36..40i16 {
+                for x in 0..40i16 {
+                    simulation.set_pixel(&(x, y).into(), id("sand")).unwrap();
+                }
+            }
+
+            for _ in 0..3 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            let stats = simulation.chunk_stats();
+            assert_eq!(stats.chunks, 9);
+            assert_eq!(stats.awake, 0);
+            assert_eq!(stats.updated_cells, 0);
+        }
+
+        #[test]
+        fn test_removing_support_wakes_sleeping_chunk() {
+            let mut simulation = simulation(40, 40);
+            for x in 18..=22i16 {
+                simulation.set_pixel(&(x, 30).into(), id("wood")).unwrap();
+            }
+            simulation
+                .set_pixel(&(20i16, 29).into(), id("sand"))
+                .unwrap();
+            for _ in 0..3 {
+                simulation.handle_ticks().unwrap();
+            }
+            assert_eq!(simulation.chunk_stats().awake, 0);
+
+            simulation.remove_pixel(&(20i16, 30).into()).unwrap();
+            for _ in 0..20 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(
+                simulation
+                    .grid
+                    .get(&(20i16, 39).into())
+                    .map(|cell| cell.material),
+                Some(id("sand"))
+            );
+        }
+
+        #[test]
+        fn test_falling_particles_wake_the_chunks_they_enter() {
+            let mut simulation = simulation(20, 60);
+            simulation.set_pixel(&(5i16, 0).into(), id("sand")).unwrap();
+
+            let mut awake = vec![];
+            for _ in 0..40 {
+                simulation.handle_ticks().unwrap();
+                awake.push(simulation.chunk_stats().awake);
+            }
+
+            // a single grain only ever keeps the chunks around it awake
+            assert!(awake[5..].iter().all(|&awake| awake <= 2), "{awake:?}");
+            assert_eq!(
+                simulation
+                    .grid
+                    .get(&(5i16, 59).into())
+                    .map(|cell| cell.material),
+                Some(id("sand"))
+            );
+        }
+
+        #[test]
+        fn test_particles_fall_into_the_void() {
+            let mut simulation = simulation(5, 10);
+            simulation.set_boundaries(Boundaries {
+                bottom: Boundary::Void,
+                ..Boundaries::default()
+            });
+            simulation.set_pixel(&(2i16, 0).into(), id("sand")).unwrap();
+            simulation.set_pixel(&(0i16, 9).into(), id("wood")).unwrap();
+
+            for _ in 0..20 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(simulation.particle_count(), 1);
+        }
+
+        #[test]
+        fn test_particles_wrap_around() {
+            let mut simulation = simulation(5, 10);
+            simulation.set_boundaries(Boundaries::all(Boundary::Wrap));
+            // a floor across the middle catches the grain once it comes back in at the top
+            for x in 0..5i16 {
+                simulation.set_pixel(&(x, 5).into(), id("wood")).unwrap();
+            }
+            simulation.set_pixel(&(2i16, 7).into(), id("sand")).unwrap();
+
+            for _ in 0..20 {
+                simulation.handle_ticks().unwrap();
+            }
+
+            assert_eq!(
+                simulation
+                    .grid
+                    .get(&(2i16, 4).into())
+                    .map(|cell| cell.material),
+                Some(id("sand"))
+            );
+        }
+
+        #[test]
+        fn test_boundary_key_cycles_modes() {
+            let mut simulation = simulation(5, 5);
+            let key = KeyEvent::from(KeyCode::Char('b'));
+            let mut modes = vec![];
+            for _ in 0..3 {
+                simulation.handle_keyboard_event(&key).unwrap();
+                modes.push(simulation.boundaries().bottom);
+            }
+
+            assert_eq!(modes, vec![Boundary::Void, Boundary::Wrap, Boundary::Wall]);
+        }
+
+        /// Sand over water across several chunks, counts the particles of each material after
+        /// `ticks` ticks.
+        #[cfg(feature = "parallel")]
+        fn run_mixed(parallel: bool, seed: u64, ticks: usize) -> Vec<(String, usize)> {
+            let mut simulation = Simulation::with_seed(MaterialRegistry::default(), seed);
+            simulation.set_parallel(parallel);
+            simulation.update_window_size(Window {
+                height: 50,
+                width: 70,
+                x: 0,
+                y: 0,
+            });
+            let (sand, water) = (id("sand"), id("water"));
+            for y in 0..40i16 {
+                for x in (0..70i16).filter(|x| x % 3 != 0) {
+                    let material = if y < 20 { sand } else { water };
+                    simulation.set_pixel(&(x, y).into(), material).unwrap();
+                }
+            }
+
+            for _ in 0..ticks {
+                simulation.handle_ticks().unwrap();
+            }
+            ["sand", "water"]
+                .into_iter()
+                .map(|name| {
+                    let material = id(name);
+                    let count = simulation
+                        .grid
+                        .iter()
+                        .filter(|(_, cell)| cell.material == material)
+                        .count();
+                    (name.to_string(), count)
+                })
+                .collect()
+        }
+
+        #[cfg(feature = "parallel")]
+        #[test]
+        fn test_parallel_ticks_conserve_particles() {
+            let single = run_mixed(false, 3, 60);
+            let parallel = run_mixed(true, 3, 60);
+
+            assert_eq!(
+                single,
+                vec![("sand".to_string(), 920), ("water".to_string(), 920)]
+            );
+            assert_eq!(parallel, single);
+        }
+
+        #[cfg(feature = "parallel")]
+        #[test]
+        fn test_parallel_ticks_are_deterministic() {
+            let run = |seed| {
+                let mut simulation = Simulation::with_seed(MaterialRegistry::default(), seed);
+                simulation.update_window_size(Window {
+                    height: 40,
+                    width: 60,
+                    x: 0,
+                    y: 0,
+                });
+                let (sand, water) = (id("sand"), id("water"));
+                for y in 0..20i16 {
+                    for x in 0..60i16 {
+                        let material = if (x + y) % 2 == 0 { sand } else { water };
+                        simulation.set_pixel(&(x, y).into(), material).unwrap();
+                    }
+                }
+                for _ in 0..40 {
+                    simulation.handle_ticks().unwrap();
+                }
+                simulation
+                    .grid
+                    .iter()
+                    .map(|(pos, cell)| format!("{pos:?} {cell:?}"))
+                    .collect::<Vec<_>>()
+            };
 
-            let cell_opt = Simulation::find_cell((3i16, 3).into(), WIDTH, HEIGHT, &hash_map);
-            assert!(matches!(*cell_opt.unwrap(), Cell::Border));
+            assert_eq!(run(9), run(9));
         }
     }
 }
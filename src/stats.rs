@@ -0,0 +1,98 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Span of time rates are measured over.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+/// Number of durations an average is taken over.
+const AVERAGE_SAMPLES: usize = 30;
+
+/// Measures how often something happens per second, like ticks or rendered frames.
+#[derive(Clone, Debug, Default)]
+pub struct RateCounter {
+    /// Points in time of the events within the last [`RATE_WINDOW`].
+    events: VecDeque<Instant>,
+}
+
+impl RateCounter {
+    /// Counts an event that happened at `now`.
+    pub fn record(&mut self, now: Instant) {
+        self.events.push_back(now);
+        self.forget_before(now);
+    }
+
+    /// Events per second, as of `now`.
+    pub fn rate(&self, now: Instant) -> f64 {
+        let recent = self
+            .events
+            .iter()
+            .filter(|event| now.duration_since(**event) < RATE_WINDOW)
+            .count();
+        recent as f64 / RATE_WINDOW.as_secs_f64()
+    }
+
+    fn forget_before(&mut self, now: Instant) {
+        while self
+            .events
+            .front()
+            .is_some_and(|event| now.duration_since(*event) >= RATE_WINDOW)
+        {
+            self.events.pop_front();
+        }
+    }
+}
+
+/// Rolling average of the last few measured durations.
+#[derive(Clone, Debug, Default)]
+pub struct DurationAverage {
+    samples: VecDeque<Duration>,
+}
+
+impl DurationAverage {
+    pub fn record(&mut self, duration: Duration) {
+        if self.samples.len() == AVERAGE_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(duration);
+    }
+
+    /// The average of the recorded durations, zero if there are none.
+    pub fn average(&self) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_counts_events_of_last_second() {
+        let start = Instant::now();
+        let mut counter = RateCounter::default();
+        for i in 0..30 {
+            counter.record(start + Duration::from_millis(i * 50));
+        }
+
+        // 1.45 s after the start only the events of the last second remain
+        assert_eq!(counter.rate(start + Duration::from_millis(1450)), 20.0);
+        assert_eq!(counter.rate(start + Duration::from_secs(5)), 0.0);
+    }
+
+    #[test]
+    fn test_average_forgets_old_samples() {
+        let mut average = DurationAverage::default();
+        assert_eq!(average.average(), Duration::ZERO);
+
+        average.record(Duration::from_secs(100));
+        for _ in 0..AVERAGE_SAMPLES {
+            average.record(Duration::from_millis(2));
+        }
+
+        assert_eq!(average.average(), Duration::from_millis(2));
+    }
+}
@@ -0,0 +1,288 @@
+use color_eyre::eyre::Result;
+use rand::Rng;
+
+use crate::{
+    chunk::MAX_REACH,
+    coord::{Direction, Vec2},
+    grid::{Grid, Neighbourhood},
+    material::MaterialRegistry,
+    particle::{Action, Cell, SimulationRng},
+    reaction::Product,
+};
+
+/// Speed a falling cell gains per tick.
+const GRAVITY: f32 = 0.25;
+/// Terminal velocity of falling cells in cells per tick.
+pub const MAX_VELOCITY: f32 = 4.0;
+
+/// Applies the particle rules to the cells of a grid during a single tick.
+///
+/// The grid may be the whole world or just a region of it, so independent regions can be updated
+/// at the same time. Every change is recorded in `woken`, the caller wakes these cells for the
+/// next tick.
+pub struct Tick<'a> {
+    pub grid: &'a mut Grid,
+    pub materials: &'a MaterialRegistry,
+    pub rng: &'a mut SimulationRng,
+    /// Stamped onto every cell that gets updated, see [`Cell::clock`].
+    pub clock: u8,
+    /// Positions whose surroundings changed.
+    pub woken: Vec<Vec2>,
+}
+
+impl Tick<'_> {
+    fn wake(&mut self, pos: &Vec2) {
+        self.woken.push(pos.clone());
+    }
+
+    /// Updates the cell at `pos`, unless it was already updated in this tick.
+    pub fn update_cell(&mut self, pos: Vec2) -> Result<()> {
+        let Some(cell) = self.grid.get_mut(&pos) else {
+            return Ok(());
+        };
+        if cell.clock == self.clock {
+            // either the cell moved here this tick or the clock wrapped around while it slept,
+            // look at it again next tick to be sure
+            self.wake(&pos);
+            return Ok(());
+        }
+        cell.age = cell.age.saturating_add(1);
+        match cell.lifetime {
+            Some(lifetime) if lifetime <= 1 => {
+                self.grid.remove(&pos);
+                self.wake(&pos);
+                return Ok(());
+            }
+            Some(lifetime) => {
+                cell.lifetime = Some(lifetime - 1);
+                self.wake(&pos);
+            }
+            None => {}
+        }
+        if self.change_phase(&pos)? || self.react(&pos)? {
+            return Ok(());
+        }
+        self.emit(&pos)?;
+        let Some(cell) = self.grid.get(&pos) else {
+            return Ok(());
+        };
+        let neighbours = Neighbourhood::new(self.grid, self.materials, pos.clone());
+        let Ok(action) = cell.update(&neighbours, self.rng) else {
+            return Ok(());
+        };
+        match action {
+            Action::None => {
+                if let Some(cell) = self.grid.get_mut(&pos) {
+                    cell.velocity = 0.0;
+                    cell.clock = self.clock;
+                }
+            }
+            Action::Wait => {
+                if let Some(cell) = self.grid.get_mut(&pos) {
+                    cell.clock = self.clock;
+                }
+                self.wake(&pos);
+            }
+            Action::Replace(mut new_cell) => {
+                new_cell.clock = self.clock;
+                self.grid.insert(&pos, new_cell)?;
+                self.wake(&pos);
+            }
+            Action::Move(direction, distance) => self.move_cell(pos, direction, distance)?,
+            Action::Swap(direction) => self.swap_cells(pos, direction)?,
+            Action::Grow(direction) => {
+                let material = cell.material;
+                if let Some(cell) = self.grid.get_mut(&pos) {
+                    cell.clock = self.clock;
+                }
+                self.place_product(&(&pos + Vec2::from(direction)), Product::Material(material))?;
+            }
+            Action::Vanish => {
+                self.grid.remove(&pos);
+                self.wake(&pos);
+            }
+        }
+        Ok(())
+    }
+
+    /// Melts, boils, ignites, freezes or condenses the cell at `pos` if its temperature crossed one
+    /// of the thresholds of its material. Returns whether it did.
+    ///
+    /// The new particle keeps the temperature of the old one, unless its material is hotter after
+    /// heating or colder after cooling. Either way it cannot flip right back.
+    fn change_phase(&mut self, pos: &Vec2) -> Result<bool> {
+        let Some(cell) = self.grid.get(pos) else {
+            return Ok(false);
+        };
+        let definition = self.materials.get(cell.material);
+        let temperature = cell.temperature;
+        let (becomes, temperature) = match (definition.heated, definition.cooled) {
+            (Some(heated), _) if temperature > heated.temperature => {
+                let becomes = self.materials.get(heated.becomes);
+                (heated.becomes, temperature.max(becomes.temperature))
+            }
+            (_, Some(cooled)) if temperature < cooled.temperature => {
+                let becomes = self.materials.get(cooled.becomes);
+                (cooled.becomes, temperature.min(becomes.temperature))
+            }
+            _ => return Ok(false),
+        };
+
+        let cell = Cell {
+            temperature,
+            clock: self.clock,
+            ..Cell::new(becomes, self.materials, &mut self.rng)
+        };
+        self.grid.insert(pos, cell)?;
+        self.wake(pos);
+        Ok(true)
+    }
+
+    /// Lets the cell at `pos` react with one of its neighbours, see [`MaterialRegistry::reactions`].
+    ///
+    /// At most one reaction takes place per cell and tick. Returns whether one did.
+    fn react(&mut self, pos: &Vec2) -> Result<bool> {
+        let Some(cell) = self.grid.get(pos) else {
+            return Ok(false);
+        };
+        let neighbours = Neighbourhood::new(self.grid, self.materials, pos.clone());
+        let mut possible = false;
+        let reaction = neighbours.iter().find_map(|(direction, other)| {
+            let reactions = self.materials.reactions(cell.material, other.material);
+            possible |= !reactions.is_empty();
+            // particles created during this tick do not react yet, otherwise a single reaction
+            // could sweep through a whole row of particles in one tick
+            if other.age == 0 {
+                return None;
+            }
+            reactions
+                .iter()
+                .find(|reaction| self.rng.random_bool(reaction.chance as f64))
+                .map(|reaction| (direction, reaction.clone()))
+        });
+        let Some((direction, reaction)) = reaction else {
+            if possible {
+                // keep trying until the reaction happens
+                self.wake(pos);
+            }
+            return Ok(false);
+        };
+
+        self.place_product(pos, reaction.becomes)?;
+        self.place_product(&(pos + Vec2::from(direction)), reaction.other_becomes)?;
+        Ok(true)
+    }
+
+    fn place_product(&mut self, pos: &Vec2, product: Product) -> Result<()> {
+        let pos = &self.grid.wrap(pos);
+        self.wake(pos);
+        match product {
+            Product::Unchanged => {
+                if let Some(cell) = self.grid.get_mut(pos) {
+                    cell.clock = self.clock;
+                }
+            }
+            Product::Empty => {
+                self.grid.remove(pos);
+            }
+            // the border and the void may take part in a reaction, but they never change
+            Product::Material(_) if !self.grid.contains(pos) => {}
+            Product::Material(material) => {
+                let cell = Cell {
+                    clock: self.clock,
+                    ..Cell::new(material, self.materials, &mut self.rng)
+                };
+                self.grid.insert(pos, cell)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets the cell at `pos` give off a particle into the empty cell above, see
+    /// [`MaterialDef::emits`](crate::material::MaterialDef::emits).
+    fn emit(&mut self, pos: &Vec2) -> Result<()> {
+        let Some(cell) = self.grid.get(pos) else {
+            return Ok(());
+        };
+        let Some(emission) = self.materials.get(cell.material).emits else {
+            return Ok(());
+        };
+        let above = self.grid.wrap(&(pos + Vec2::from(Direction::Up)));
+        if !self.grid.contains(&above) || self.grid.get(&above).is_some() {
+            return Ok(());
+        }
+        if !self.rng.random_bool(emission.chance as f64) {
+            self.wake(pos);
+            return Ok(());
+        }
+        self.place_product(&above, Product::Material(emission.material))
+    }
+
+    /// Moves the cell at `pos` up to `distance` cells towards `direction`, as long as the way is
+    /// free.
+    ///
+    /// Falling cells pick up speed, a blocked cell loses all of its speed.
+    fn move_cell(&mut self, pos: Vec2, direction: Direction, distance: u16) -> Result<()> {
+        debug_assert!(
+            distance <= MAX_REACH as u16,
+            "a cell may move at most {MAX_REACH} cells per tick, not {distance}"
+        );
+        let Some(cell) = self.grid.get_mut(&pos) else {
+            return Ok(());
+        };
+        cell.clock = self.clock;
+
+        let step = Vec2::from(direction);
+        let mut target = pos.clone();
+        for _ in 0..distance {
+            let next = self.grid.wrap(&(&target + &step));
+            if self.grid.is_void(&next) {
+                // the cell drops out of the world
+                self.grid.remove(&pos);
+                self.wake(&pos);
+                return Ok(());
+            }
+            if !self.grid.contains(&next) || self.grid.get(&next).is_some() {
+                break;
+            }
+            target = next;
+        }
+        if target == pos {
+            if let Some(cell) = self.grid.get_mut(&pos) {
+                cell.velocity = 0.0;
+            }
+            return Ok(());
+        }
+
+        self.grid.swap(&pos, &target)?;
+        self.wake(&pos);
+        self.wake(&target);
+        if direction == Direction::Down
+            && let Some(cell) = self.grid.get_mut(&target)
+        {
+            cell.velocity = (cell.velocity + GRAVITY).min(MAX_VELOCITY);
+        }
+        Ok(())
+    }
+    /// Trades places of the cell at `pos` and its neighbour in `direction` in a single step.
+    ///
+    /// Both cells count as updated afterwards, a displaced cell does not get to move again.
+    fn swap_cells(&mut self, pos: Vec2, direction: Direction) -> Result<()> {
+        let other = self.grid.wrap(&(&pos + Vec2::from(direction)));
+        if self.grid.get(&other).is_none() {
+            return self.move_cell(pos, direction, 1);
+        }
+        self.grid.swap(&pos, &other)?;
+        self.wake(&pos);
+        self.wake(&other);
+        if let Some(cell) = self.grid.get_mut(&other) {
+            // wading through a fluid slows falling cells down
+            cell.velocity = 0.0;
+            cell.clock = self.clock;
+        }
+        if let Some(cell) = self.grid.get_mut(&pos) {
+            cell.clock = self.clock;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,97 @@
-use ratatui::{buffer::Buffer, layout::Rect, style::Stylize, widgets::Widget};
+use std::time::Instant;
+
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Widget},
+};
+
+use crate::{
+    app::App,
+    brush::BrushShape,
+    coord::Vec2,
+    material::{MaterialId, MaterialRegistry},
+    simulation_widget::Simulation,
+};
+
+/// Width of the material palette next to the simulation, including its border.
+pub const PALETTE_WIDTH: u16 = 16;
+
+impl App {
+    /// Splits the terminal into the simulation area, the palette to its right and the status bar
+    /// below both.
+    pub fn layout(area: Rect) -> [Rect; 3] {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let [simulation, palette] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(PALETTE_WIDTH)]).areas(main);
+        [simulation, palette, status]
+    }
+
+    /// The line below the simulation: what it is doing and what the mouse paints with.
+    fn status_line(&self) -> Line<'_> {
+        let now = Instant::now();
+        let simulation = &self.simulation_widget;
+        let materials = simulation.materials();
+        let separator = || Span::raw(" │ ").dark_gray();
+
+        let mut spans = vec![];
+        if self.simulation_paused {
+            spans.push(Span::raw("PAUSED").black().on_yellow().bold());
+            spans.push(separator());
+        }
+        if let Some(recorder) = &self.recorder {
+            let frames = format!("REC {}/{}", recorder.frames().len(), recorder.max_frames());
+            spans.push(Span::raw(frames).white().on_red().bold());
+            spans.push(separator());
+        }
+        if let Some(message) = &self.message {
+            spans.push(Span::raw(message.as_str()).italic());
+            spans.push(separator());
+        }
+        spans.push(Span::raw(format!("{}x ", self.speed.factor())).bold());
+        spans.push(Span::raw(format!(
+            "{:.0} tps {:.0} fps {:.2} ms/tick",
+            self.tick_rate.rate(now),
+            self.frame_rate.rate(now),
+            self.tick_time.average().as_secs_f64() * 1000.0,
+        )));
+        spans.push(separator());
+
+        let brush = simulation.brush();
+        let tool = match simulation.selected() {
+            _ if brush.is_eraser() => "eraser".to_string(),
+            Some(material) => materials.get(material).name.clone(),
+            None => "nothing".to_string(),
+        };
+        let shape = match brush.shape() {
+            BrushShape::Circle => "circle",
+            BrushShape::Square => "square",
+            BrushShape::Spray => "spray",
+        };
+        spans.push(Span::styled(tool, Style::new().fg(self.color)));
+        spans.push(Span::raw(format!(" {shape} r{}", brush.radius())));
+        spans.push(separator());
+
+        spans.push(Span::raw(format!(
+            "{} particles",
+            simulation.particle_count()
+        )));
+        for (material, count) in simulation.material_counts() {
+            let definition = materials.get(material);
+            let (r, g, b) = definition.color;
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(
+                format!("{} {count}", definition.name),
+                Style::new().fg(Color::Rgb(r, g, b)),
+            ));
+        }
+        Line::from(spans)
+    }
+}
 
-use crate::{app::App, simulation_widget::Simulation};
 impl Widget for &App {
     /// Renders the user interface widgets.
     ///
@@ -9,7 +100,19 @@ impl Widget for &App {
     // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
     // - https://github.com/ratatui/ratatui/tree/master/examples
     fn render(self, area: Rect, buf: &mut Buffer) {
-        self.simulation_widget.render(area, buf);
+        let [simulation_area, palette_area, status_area] = App::layout(area);
+        Viewport {
+            simulation: &self.simulation_widget,
+            camera: &self.camera,
+        }
+        .render(simulation_area, buf);
+        Palette {
+            materials: self.simulation_widget.materials(),
+            selected: self.simulation_widget.selected(),
+            color: self.color,
+        }
+        .render(palette_area, buf);
+        self.status_line().render(status_area, buf);
     }
 }
 
@@ -18,10 +121,155 @@ impl Widget for &Simulation {
     where
         Self: Sized,
     {
-        for (position, color) in self.iter_cells() {
-            let area = Rect::from((position, ratatui::layout::Size::new(1, 1))).clamp(area);
-            let pixel_widget = ratatui::symbols::block::FULL.fg(color);
-            pixel_widget.render(area, buf);
+        Viewport {
+            simulation: self,
+            camera: &Vec2::default(),
+        }
+        .render(area, buf);
+    }
+}
+
+/// The part of the world seen through the camera.
+pub struct Viewport<'a> {
+    pub simulation: &'a Simulation,
+    /// World position shown in the upper left corner of the area.
+    pub camera: &'a Vec2,
+}
+
+impl Widget for Viewport<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        // only the cells in view are looked at, however large the world is
+        for row in 0..area.height {
+            for column in 0..area.width {
+                let pos = Vec2 {
+                    x: self.camera.x.saturating_add(column as i16),
+                    y: self.camera.y.saturating_add(row as i16),
+                };
+                let Some(color) = self.simulation.color_at(&pos) else {
+                    continue;
+                };
+                let area = Rect::new(area.x + column, area.y + row, 1, 1);
+                let pixel_widget = ratatui::symbols::block::FULL.fg(color);
+                pixel_widget.render(area, buf);
+            }
         }
     }
 }
+
+/// Lists all materials with their colours and selection keys.
+pub struct Palette<'a> {
+    pub materials: &'a MaterialRegistry,
+    pub selected: Option<MaterialId>,
+    /// Colour of the border, the colour of the selected material.
+    pub color: Color,
+}
+
+impl Palette<'_> {
+    /// The material listed at `position` of a palette rendered into `area`.
+    pub fn material_at(
+        materials: &MaterialRegistry,
+        area: Rect,
+        position: Position,
+    ) -> Option<MaterialId> {
+        let inner = Block::bordered().inner(area);
+        if !inner.contains(position) {
+            return None;
+        }
+        materials
+            .iter()
+            .nth((position.y - inner.y) as usize)
+            .map(|(id, _)| id)
+    }
+}
+
+impl Widget for Palette<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let lines: Vec<Line> = self
+            .materials
+            .iter()
+            .enumerate()
+            .map(|(index, (id, material))| {
+                // the number keys select the first ten materials, 0 stands for the tenth
+                let key = match index {
+                    0..=9 => ((index + 1) % 10).to_string(),
+                    _ => " ".to_string(),
+                };
+                let (r, g, b) = material.color;
+                let line = Line::from(vec![
+                    Span::raw(format!("{key} ")),
+                    Span::styled("██", Style::new().fg(Color::Rgb(r, g, b))),
+                    Span::raw(format!(" {}", material.name)),
+                ]);
+                if Some(id) == self.selected {
+                    line.reversed()
+                } else {
+                    line
+                }
+            })
+            .collect();
+        Paragraph::new(lines)
+            .block(
+                Block::bordered()
+                    .title("Materials")
+                    .border_style(Style::new().fg(self.color)),
+            )
+            .render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation_widget::MaterialCanvas;
+
+    #[test]
+    fn test_viewport_shows_cells_under_camera() {
+        let mut simulation = Simulation::default();
+        simulation.set_world_size(10, 10);
+        let sand = simulation.materials().find("sand").unwrap();
+        simulation.set_pixel(&(5i16, 6).into(), sand).unwrap();
+        let area = Rect::new(2, 1, 3, 3);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 6, 5));
+
+        Viewport {
+            simulation: &simulation,
+            camera: &(4i16, 4).into(),
+        }
+        .render(area, &mut buf);
+
+        let full: Vec<(u16, u16)> = buf
+            .content()
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.symbol() == ratatui::symbols::block::FULL)
+            .map(|(idx, _)| buf.pos_of(idx))
+            .collect();
+        assert_eq!(full, [(3, 3)]);
+    }
+
+    #[test]
+    fn test_palette_click_finds_material_of_row() {
+        let materials = MaterialRegistry::default();
+        let area = Rect::new(60, 0, PALETTE_WIDTH, 20);
+
+        // the first row is the border
+        assert_eq!(
+            Palette::material_at(&materials, area, Position::new(61, 0)),
+            None
+        );
+        assert_eq!(
+            Palette::material_at(&materials, area, Position::new(65, 2)),
+            materials.find("water")
+        );
+        assert_eq!(
+            Palette::material_at(&materials, area, Position::new(61, 18)),
+            None
+        );
+    }
+}